@@ -0,0 +1,167 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Metadata for a file uploaded to a room.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Attachment {
+    pub id: String,
+    pub room: String,
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+    /// Widths of the server-generated thumbnails, smallest first.
+    #[serde(default)]
+    pub thumbnails: Vec<u32>,
+    /// Length of a recorded voice note.
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    /// Peak amplitudes (0-255) of a voice note, one per waveform bar.
+    #[serde(default)]
+    pub waveform: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn url(&self) -> String {
+        format!("/uploads/{}/{}", self.room, self.id)
+    }
+
+    /// The smallest thumbnail at least `width` wide, falling back to the full image.
+    pub fn thumbnail_url(&self, width: u32) -> String {
+        match self.thumbnails.iter().find(|&&w| w >= width) {
+            Some(w) => format!("{}/thumb/{}", self.url(), w),
+            None => self.url(),
+        }
+    }
+
+    pub fn is_audio(&self) -> bool {
+        self.mime.starts_with("audio/")
+    }
+
+    pub fn is_image(&self) -> bool {
+        matches!(
+            self.mime.as_str(),
+            "image/png" | "image/jpeg" | "image/gif" | "image/webp"
+        )
+    }
+}
+
+/// Shows an image attachment inline with a click-to-open lightbox, a player for voice notes,
+/// or a download link for other files.
+#[component]
+pub fn AttachmentPreview(attachment: Attachment) -> impl IntoView {
+    if attachment.is_audio() && attachment.duration_ms.is_some() {
+        return view! { <VoiceNote attachment=attachment/> }.into_any();
+    }
+    if !attachment.is_image() {
+        let url = attachment.url();
+        return view! {
+            <a href=url target="_blank" class="font-semibold text-blue-600 hover:underline truncate">
+                {attachment.name}
+            </a>
+        }
+        .into_any();
+    }
+
+    let (open, set_open) = signal(false);
+    let thumbnail_url = attachment.thumbnail_url(480);
+    let full_url = attachment.url();
+    let lightbox_alt = attachment.name.clone();
+
+    view! {
+        <button class="block" on:click=move |_| set_open.set(true)>
+            // Native lazy loading defers the fetch until the image scrolls into view
+            <img
+                src=thumbnail_url
+                alt=attachment.name
+                loading="lazy"
+                class="max-h-48 rounded-lg shadow-sm hover:opacity-90 transition-opacity"
+            />
+        </button>
+        <Show when=move || open.get()>
+            <div
+                class="fixed inset-0 z-50 flex items-center justify-center p-4 bg-black/80 backdrop-blur-sm cursor-zoom-out"
+                on:click=move |_| set_open.set(false)
+            >
+                <img src=full_url.clone() alt=lightbox_alt.clone() class="max-w-full max-h-full rounded-lg shadow-2xl"/>
+            </div>
+        </Show>
+    }
+    .into_any()
+}
+
+/// Compact voice note player: play/pause, the waveform filling in as it plays, and the length.
+#[component]
+pub fn VoiceNote(attachment: Attachment) -> impl IntoView {
+    let audio_ref = NodeRef::<leptos::html::Audio>::new();
+    let (playing, set_playing) = signal(false);
+    let (position, set_position) = signal(0.0);
+    let duration_ms = attachment.duration_ms.unwrap_or_default();
+    let bars = attachment.waveform.len().max(1);
+
+    let toggle = move |_| {
+        if let Some(audio) = audio_ref.get() {
+            if audio.paused() {
+                let _ = audio.play();
+            } else {
+                let _ = audio.pause();
+            }
+        }
+    };
+
+    // Recorded WebM often reports an infinite duration, so progress uses the stored length
+    let on_time_update = move |_| {
+        if let Some(audio) = audio_ref.get() {
+            if duration_ms > 0 {
+                set_position.set((audio.current_time() * 1000.0 / duration_ms as f64).min(1.0));
+            }
+        }
+    };
+
+    view! {
+        <div class="flex items-center gap-3">
+            <audio
+                node_ref=audio_ref
+                src=attachment.url()
+                preload="metadata"
+                on:play=move |_| set_playing.set(true)
+                on:pause=move |_| set_playing.set(false)
+                on:ended=move |_| {
+                    set_playing.set(false);
+                    set_position.set(0.0);
+                }
+                on:timeupdate=on_time_update
+            ></audio>
+            <button
+                type="button"
+                on:click=toggle
+                title=move || if playing.get() { "Pause" } else { "Play" }
+                class="w-8 h-8 flex items-center justify-center rounded-full bg-gradient-to-r from-blue-600 to-purple-600 text-white shadow-sm active:scale-95 transition-transform"
+            >
+                {move || if playing.get() {
+                    view! { <svg class="w-4 h-4" fill="currentColor" viewBox="0 0 24 24"><path d="M6 5h4v14H6zM14 5h4v14h-4z"></path></svg> }.into_any()
+                } else {
+                    view! { <svg class="w-4 h-4" fill="currentColor" viewBox="0 0 24 24"><path d="M8 5v14l11-7z"></path></svg> }.into_any()
+                }}
+            </button>
+            <div class="flex items-center gap-px h-8">
+                {attachment.waveform.iter().enumerate().map(|(i, &peak)| {
+                    let played = move || (i as f64 + 0.5) / bars as f64 <= position.get();
+                    view! {
+                        <div
+                            class="w-1 rounded-full"
+                            class=("bg-blue-600", played)
+                            class=("bg-gray-300", move || !played())
+                            style:height=format!("{}%", (u32::from(peak) * 100 / 255).max(10))
+                        ></div>
+                    }
+                }).collect_view()}
+            </div>
+            <span class="text-xs font-medium text-gray-500 tabular-nums">{format_duration(duration_ms)}</span>
+        </div>
+    }
+}
+
+fn format_duration(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
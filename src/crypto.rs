@@ -0,0 +1,740 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use js_sys::{Array, Object, Promise, Reflect, Uint8Array};
+use leptos::prelude::window;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AesDerivedKeyParams, AesGcmParams, CryptoKey, EcKeyGenParams, EcKeyImportParams,
+    EcdhKeyDeriveParams, EcdsaParams, HkdfParams, SubtleCrypto,
+};
+
+const SECRET_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const ENVELOPE_VERSION: &str = "e2ee1";
+const KEY_INFO: &[u8] = b"chat-stream room key v1";
+const WRAP_INFO: &[u8] = b"chat-stream key wrap v1";
+const FINGERPRINT_INFO: &[u8] = b"chat-stream fingerprint v1";
+const DEVICE_FINGERPRINT_INFO: &[u8] = b"chat-stream device fingerprint v2";
+const EPOCH_SIGNATURE_INFO: &[u8] = b"chat-stream key epoch v1";
+/// Marks a room code as end-to-end encrypted, so a client holding only the code knows to
+/// refuse the room instead of showing it in the clear.
+const ENCRYPTED_ROOM_PREFIX: &str = "e2ee-";
+
+/// The code for a new end-to-end encrypted room with id `id`.
+pub fn encrypted_room_code(id: &str) -> String {
+    format!("{}{}", ENCRYPTED_ROOM_PREFIX, id)
+}
+
+/// Whether `code` names an end-to-end encrypted room, which can only be joined with its key.
+pub fn is_encrypted_room(code: &str) -> bool {
+    code.starts_with(ENCRYPTED_ROOM_PREFIX)
+}
+
+/// Creates the secret for a new encrypted room, encoded for the invite link fragment.
+pub fn generate_room_secret() -> Result<String, String> {
+    Ok(URL_SAFE_NO_PAD.encode(random_bytes::<SECRET_LEN>()?))
+}
+
+/// The invite link fragment carrying `secret`. Browsers never send the fragment to the server.
+pub fn invite_fragment(secret: &str) -> String {
+    format!("#key={}", secret)
+}
+
+/// Reads the room secret from an invite link fragment such as `#key=...`.
+pub fn secret_from_fragment(hash: &str) -> Option<String> {
+    hash.trim_start_matches('#')
+        .split('&')
+        .find_map(|param| param.strip_prefix("key="))
+        .filter(|secret| decode_secret(secret).is_ok())
+        .map(str::to_string)
+}
+
+/// The AES-256-GCM key of one epoch of an encrypted room, derived from its secret with HKDF.
+/// The key is not extractable, so it can't be read back out of the browser.
+///
+/// Epoch 0 comes from the invite link; each rotation starts a new epoch with a fresh secret.
+#[derive(Clone)]
+pub struct RoomKey {
+    key: CryptoKey,
+    room: String,
+    epoch: u32,
+}
+
+impl RoomKey {
+    pub async fn derive(room: &str, epoch: u32, secret: &str) -> Result<Self, String> {
+        // Salting with the room and epoch gives each its own key even if a secret is reused
+        let key = derive_aes_key(
+            &decode_secret(secret)?,
+            KEY_INFO,
+            &associated_data(room, epoch),
+        )
+        .await?;
+
+        Ok(Self {
+            key,
+            room: room.to_string(),
+            epoch,
+        })
+    }
+
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Encrypts a message into an envelope string, the only form the server ever stores.
+    pub async fn encrypt(&self, plaintext: &str) -> Result<String, String> {
+        seal(&self.key, &self.room, self.epoch, plaintext.as_bytes()).await
+    }
+
+    /// Decrypts an envelope made by `encrypt`, failing if it was tampered with or made with another key.
+    pub async fn decrypt(&self, envelope: &str) -> Result<String, String> {
+        let plaintext = open(&self.key, &self.room, self.epoch, envelope).await?;
+        String::from_utf8(plaintext).map_err(|e| e.to_string())
+    }
+}
+
+/// The key epoch an envelope was encrypted under, so the reader knows which `RoomKey` to use.
+pub fn envelope_epoch(envelope: &str) -> Option<u32> {
+    parse_envelope(envelope).ok().map(|(epoch, _, _)| epoch)
+}
+
+/// A short code that members compare out of band to confirm they all hold the same room key.
+pub async fn fingerprint(room: &str, secret: &str) -> Result<String, String> {
+    let mut input = FINGERPRINT_INFO.to_vec();
+    input.extend_from_slice(room.as_bytes());
+    input.extend_from_slice(&decode_secret(secret)?);
+    digest_fingerprint(&input).await
+}
+
+/// This browser's long-term identity: an ECDH P-256 key pair for receiving room keys and an
+/// ECDSA P-256 key pair for signing the key epochs it starts. Neither private half is
+/// extractable. IndexedDB can store the `CryptoKey`s themselves, so they never exist as bytes.
+#[derive(Clone)]
+pub struct DeviceIdentity {
+    pub id: String,
+    pub private_key: CryptoKey,
+    /// Raw (uncompressed point) public key.
+    pub public_key: Vec<u8>,
+    pub signing_key: CryptoKey,
+    /// Raw (uncompressed point) public half of `signing_key`.
+    pub signing_public_key: Vec<u8>,
+}
+
+impl DeviceIdentity {
+    pub async fn generate() -> Result<Self, String> {
+        let (private_key, public_key) = generate_key_pair("ECDH", &["deriveBits"]).await?;
+        let (signing_key, signing_public_key) =
+            generate_key_pair("ECDSA", &["sign", "verify"]).await?;
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            private_key,
+            public_key,
+            signing_key,
+            signing_public_key,
+        })
+    }
+
+    /// The public half, as shared with the rooms this device is a member of.
+    pub fn public(&self) -> DevicePublicKey {
+        DevicePublicKey {
+            device: self.id.clone(),
+            key: URL_SAFE_NO_PAD.encode(&self.public_key),
+            signing_key: URL_SAFE_NO_PAD.encode(&self.signing_public_key),
+        }
+    }
+
+    /// A short code other members compare to confirm the public keys really belong to this device.
+    pub async fn fingerprint(&self) -> Result<String, String> {
+        device_fingerprint(&self.public()).await
+    }
+
+    /// The record kept in IndexedDB. It is a plain object rather than serde output because
+    /// the private `CryptoKey` has to be stored as is.
+    pub fn to_js_value(&self) -> JsValue {
+        let record = Object::new();
+        let _ = Reflect::set(&record, &"id".into(), &JsValue::from_str(&self.id));
+        let _ = Reflect::set(&record, &"private_key".into(), &self.private_key);
+        let _ = Reflect::set(
+            &record,
+            &"public_key".into(),
+            &Uint8Array::from(&self.public_key[..]),
+        );
+        let _ = Reflect::set(&record, &"signing_key".into(), &self.signing_key);
+        let _ = Reflect::set(
+            &record,
+            &"signing_public_key".into(),
+            &Uint8Array::from(&self.signing_public_key[..]),
+        );
+        record.into()
+    }
+
+    pub fn from_js_value(record: &JsValue) -> Result<Self, String> {
+        let field = |name: &str| Reflect::get(record, &name.into()).map_err(js_error);
+        Ok(Self {
+            id: field("id")?
+                .as_string()
+                .ok_or("Identity record has no id")?,
+            private_key: field("private_key")?
+                .dyn_into()
+                .map_err(|_| "Identity record has no private key".to_string())?,
+            public_key: field("public_key")?
+                .dyn_into::<Uint8Array>()
+                .map_err(|_| "Identity record has no public key".to_string())?
+                .to_vec(),
+            signing_key: field("signing_key")?
+                .dyn_into()
+                .map_err(|_| "Identity record has no signing key".to_string())?,
+            signing_public_key: field("signing_public_key")?
+                .dyn_into::<Uint8Array>()
+                .map_err(|_| "Identity record has no public signing key".to_string())?
+                .to_vec(),
+        })
+    }
+}
+
+/// A member device's public identity keys, base64url encoded.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DevicePublicKey {
+    pub device: String,
+    pub key: String,
+    pub signing_key: String,
+}
+
+/// The fingerprint of a device's public keys, as shown by `DeviceIdentity::fingerprint` on
+/// that device.
+pub async fn device_fingerprint(device: &DevicePublicKey) -> Result<String, String> {
+    let mut input = DEVICE_FINGERPRINT_INFO.to_vec();
+    input.extend_from_slice(&decode(&device.key)?);
+    input.extend_from_slice(&decode(&device.signing_key)?);
+    digest_fingerprint(&input).await
+}
+
+/// A room secret encrypted to one member device, using a one-off ECDH key pair.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct WrappedSecret {
+    pub device: String,
+    pub ephemeral_key: String,
+    pub envelope: String,
+}
+
+/// One generation of a room's key, wrapped for each member device at the time it was made and
+/// signed by the device that made it. Whoever relays it can't add, drop or swap wrapped keys
+/// without breaking the signature.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct KeyEpoch {
+    pub epoch: u32,
+    pub wrapped: Vec<WrappedSecret>,
+    pub signer: DevicePublicKey,
+    /// ECDSA P-256 / SHA-256 signature over the room, epoch, signer and wrapped keys.
+    pub signature: String,
+}
+
+/// Starts key epoch `epoch` with a fresh secret wrapped for `members` and signed by `signer`,
+/// returning the secret for the caller's own use. Devices not in `members` - because they left
+/// or were removed - get nothing they can unwrap, so they can't read anything sent under the
+/// new epoch. Nothing calls this on leave yet: there is no member roster to wrap for, nor a
+/// relay to deliver the epoch, so a member who leaves keeps the current secret.
+pub async fn rotate_room_secret(
+    room: &str,
+    epoch: u32,
+    signer: &DeviceIdentity,
+    members: &[DevicePublicKey],
+) -> Result<(String, KeyEpoch), String> {
+    let secret = random_bytes::<SECRET_LEN>()?;
+    let mut wrapped = Vec::with_capacity(members.len());
+    for member in members {
+        wrapped.push(wrap_secret(room, epoch, &secret, member).await?);
+    }
+    let mut key_epoch = KeyEpoch {
+        epoch,
+        wrapped,
+        signer: signer.public(),
+        signature: String::new(),
+    };
+    sign_key_epoch(room, signer, &mut key_epoch).await?;
+    Ok((URL_SAFE_NO_PAD.encode(secret), key_epoch))
+}
+
+/// Recovers the room secret that `key_epoch` wrapped for this device. The epoch must carry a
+/// valid signature from the device whose fingerprint is `signer_fingerprint`, i.e. one the
+/// user has compared out of band; anything else is rejected before it is unwrapped.
+pub async fn unwrap_room_secret(
+    room: &str,
+    identity: &DeviceIdentity,
+    key_epoch: &KeyEpoch,
+    signer_fingerprint: &str,
+) -> Result<String, String> {
+    verify_key_epoch(room, key_epoch, signer_fingerprint).await?;
+    let wrapped = key_epoch
+        .wrapped
+        .iter()
+        .find(|wrapped| wrapped.device == identity.id)
+        .ok_or("This device has no key for the room")?;
+
+    let ephemeral_key =
+        import_public_key(&decode(&wrapped.ephemeral_key)?, &ecdh_import_params(), &[]).await?;
+    let key = wrapping_key(room, key_epoch.epoch, &identity.private_key, &ephemeral_key).await?;
+    let secret = open(&key, room, key_epoch.epoch, &wrapped.envelope)
+        .await
+        .map_err(|_| "The room key could not be unwrapped".to_string())?;
+    Ok(URL_SAFE_NO_PAD.encode(secret))
+}
+
+async fn sign_key_epoch(
+    room: &str,
+    signer: &DeviceIdentity,
+    key_epoch: &mut KeyEpoch,
+) -> Result<(), String> {
+    let signature = resolve(subtle()?.sign_with_object_and_u8_array(
+        &ecdsa_params(),
+        &signer.signing_key,
+        &key_epoch_signing_input(room, key_epoch),
+    ))
+    .await?;
+    key_epoch.signature = URL_SAFE_NO_PAD.encode(Uint8Array::new(&signature).to_vec());
+    Ok(())
+}
+
+async fn verify_key_epoch(
+    room: &str,
+    key_epoch: &KeyEpoch,
+    signer_fingerprint: &str,
+) -> Result<(), String> {
+    if key_epoch.signature.is_empty() {
+        return Err("The room key is not signed".to_string());
+    }
+    if device_fingerprint(&key_epoch.signer).await? != signer_fingerprint {
+        return Err("The room key was signed by an unverified device".to_string());
+    }
+
+    let params = EcKeyImportParams::new("ECDSA");
+    params.set_named_curve("P-256");
+    let signing_key = import_public_key(
+        &decode(&key_epoch.signer.signing_key)?,
+        &params,
+        &["verify"],
+    )
+    .await?;
+    let valid = resolve(subtle()?.verify_with_object_and_u8_array_and_u8_array(
+        &ecdsa_params(),
+        &signing_key,
+        &decode(&key_epoch.signature)?,
+        &key_epoch_signing_input(room, key_epoch),
+    ))
+    .await?;
+    if valid.as_bool() != Some(true) {
+        return Err("The room key signature is invalid".to_string());
+    }
+    Ok(())
+}
+
+/// Every field is length-prefixed, so no two different epochs produce the same input.
+fn key_epoch_signing_input(room: &str, key_epoch: &KeyEpoch) -> Vec<u8> {
+    let mut input = EPOCH_SIGNATURE_INFO.to_vec();
+    let mut push = |field: &[u8]| {
+        input.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        input.extend_from_slice(field);
+    };
+    push(room.as_bytes());
+    push(&key_epoch.epoch.to_be_bytes());
+    push(key_epoch.signer.device.as_bytes());
+    push(key_epoch.signer.key.as_bytes());
+    push(key_epoch.signer.signing_key.as_bytes());
+    push(&(key_epoch.wrapped.len() as u32).to_be_bytes());
+    for wrapped in &key_epoch.wrapped {
+        push(wrapped.device.as_bytes());
+        push(wrapped.ephemeral_key.as_bytes());
+        push(wrapped.envelope.as_bytes());
+    }
+    input
+}
+
+fn ecdsa_params() -> EcdsaParams {
+    EcdsaParams::new("ECDSA", &JsValue::from_str("SHA-256"))
+}
+
+async fn wrap_secret(
+    room: &str,
+    epoch: u32,
+    secret: &[u8],
+    member: &DevicePublicKey,
+) -> Result<WrappedSecret, String> {
+    let member_key = import_public_key(&decode(&member.key)?, &ecdh_import_params(), &[]).await?;
+    let (ephemeral_private, ephemeral_public) = generate_key_pair("ECDH", &["deriveBits"]).await?;
+    let key = wrapping_key(room, epoch, &ephemeral_private, &member_key).await?;
+
+    Ok(WrappedSecret {
+        device: member.device.clone(),
+        ephemeral_key: URL_SAFE_NO_PAD.encode(ephemeral_public),
+        envelope: seal(&key, room, epoch, secret).await?,
+    })
+}
+
+/// ECDH between one side's private key and the other's public key, stretched with HKDF.
+async fn wrapping_key(
+    room: &str,
+    epoch: u32,
+    private_key: &CryptoKey,
+    public_key: &CryptoKey,
+) -> Result<CryptoKey, String> {
+    let shared = resolve(subtle()?.derive_bits_with_object(
+        &EcdhKeyDeriveParams::new("ECDH", public_key),
+        private_key,
+        256,
+    ))
+    .await?;
+    derive_aes_key(
+        &Uint8Array::new(&shared).to_vec(),
+        WRAP_INFO,
+        &associated_data(room, epoch),
+    )
+    .await
+}
+
+async fn generate_key_pair(
+    algorithm: &str,
+    key_usages: &[&str],
+) -> Result<(CryptoKey, Vec<u8>), String> {
+    let subtle = subtle()?;
+    let pair = resolve(subtle.generate_key_with_object(
+        &EcKeyGenParams::new(algorithm, "P-256"),
+        false,
+        &usages(key_usages),
+    ))
+    .await?;
+    let private_key: CryptoKey = Reflect::get(&pair, &"privateKey".into())
+        .map_err(js_error)?
+        .unchecked_into();
+    let public_key: CryptoKey = Reflect::get(&pair, &"publicKey".into())
+        .map_err(js_error)?
+        .unchecked_into();
+
+    // Public keys stay exportable whatever the extractable flag says
+    let public_key = resolve(subtle.export_key("raw", &public_key)).await?;
+    Ok((private_key, Uint8Array::new(&public_key).to_vec()))
+}
+
+fn ecdh_import_params() -> EcKeyImportParams {
+    let params = EcKeyImportParams::new("ECDH");
+    params.set_named_curve("P-256");
+    params
+}
+
+async fn import_public_key(
+    raw: &[u8],
+    params: &EcKeyImportParams,
+    key_usages: &[&str],
+) -> Result<CryptoKey, String> {
+    Ok(resolve(subtle()?.import_key_with_object(
+        "raw",
+        &Uint8Array::from(raw),
+        params,
+        true,
+        &usages(key_usages),
+    ))
+    .await?
+    .unchecked_into())
+}
+
+async fn derive_aes_key(ikm: &[u8], info: &[u8], salt: &[u8]) -> Result<CryptoKey, String> {
+    let subtle = subtle()?;
+    let base: CryptoKey = resolve(subtle.import_key_with_str(
+        "raw",
+        &Uint8Array::from(ikm),
+        "HKDF",
+        false,
+        &usages(&["deriveKey"]),
+    ))
+    .await?
+    .unchecked_into();
+
+    let params = HkdfParams::new(
+        "HKDF",
+        &JsValue::from_str("SHA-256"),
+        &Uint8Array::from(info),
+        &Uint8Array::from(salt),
+    );
+    Ok(resolve(subtle.derive_key_with_object_and_object(
+        &params,
+        &base,
+        &AesDerivedKeyParams::new("AES-GCM", 256),
+        false,
+        &usages(&["encrypt", "decrypt"]),
+    ))
+    .await?
+    .unchecked_into())
+}
+
+async fn seal(key: &CryptoKey, room: &str, epoch: u32, plaintext: &[u8]) -> Result<String, String> {
+    let nonce = random_bytes::<NONCE_LEN>()?;
+    let ciphertext = resolve(subtle()?.encrypt_with_object_and_u8_array(
+        &gcm_params(room, epoch, &nonce),
+        key,
+        plaintext,
+    ))
+    .await?;
+
+    Ok(format!(
+        "{}.{}.{}.{}",
+        ENVELOPE_VERSION,
+        epoch,
+        URL_SAFE_NO_PAD.encode(nonce),
+        URL_SAFE_NO_PAD.encode(Uint8Array::new(&ciphertext).to_vec())
+    ))
+}
+
+async fn open(key: &CryptoKey, room: &str, epoch: u32, envelope: &str) -> Result<Vec<u8>, String> {
+    let (envelope_epoch, nonce, ciphertext) = parse_envelope(envelope)?;
+    if envelope_epoch != epoch {
+        return Err(format!(
+            "Encrypted under key epoch {}, not {}",
+            envelope_epoch, epoch
+        ));
+    }
+
+    let plaintext = resolve(subtle()?.decrypt_with_object_and_u8_array(
+        &gcm_params(room, epoch, &nonce),
+        key,
+        &ciphertext,
+    ))
+    .await
+    .map_err(|_| "Message could not be decrypted".to_string())?;
+    Ok(Uint8Array::new(&plaintext).to_vec())
+}
+
+// The room and epoch are bound as associated data so a ciphertext can't be replayed elsewhere
+fn gcm_params(room: &str, epoch: u32, nonce: &[u8]) -> AesGcmParams {
+    let params = AesGcmParams::new("AES-GCM", &Uint8Array::from(nonce));
+    params.set_additional_data(&Uint8Array::from(&associated_data(room, epoch)[..]));
+    params
+}
+
+fn associated_data(room: &str, epoch: u32) -> Vec<u8> {
+    format!("{}/{}", room, epoch).into_bytes()
+}
+
+fn parse_envelope(envelope: &str) -> Result<(u32, Vec<u8>, Vec<u8>), String> {
+    let mut parts = envelope.split('.');
+    let (Some(ENVELOPE_VERSION), Some(epoch), Some(nonce), Some(ciphertext), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return Err("Not an encrypted message".to_string());
+    };
+
+    let epoch = epoch.parse().map_err(|_| "Invalid key epoch".to_string())?;
+    let nonce = decode(nonce)?;
+    if nonce.len() != NONCE_LEN {
+        return Err("Invalid nonce".to_string());
+    }
+    Ok((epoch, nonce, decode(ciphertext)?))
+}
+
+async fn digest_fingerprint(input: &[u8]) -> Result<String, String> {
+    let digest = resolve(subtle()?.digest_with_str_and_u8_array("SHA-256", input)).await?;
+    Ok(format_fingerprint(&Uint8Array::new(&digest).to_vec()))
+}
+
+/// Six groups of five digits, read from the start of the digest.
+fn format_fingerprint(digest: &[u8]) -> String {
+    digest
+        .chunks(5)
+        .take(6)
+        .map(|chunk| {
+            let n = chunk
+                .iter()
+                .fold(0u64, |acc, &byte| (acc << 8) | u64::from(byte));
+            format!("{:05}", n % 100_000)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn decode_secret(secret: &str) -> Result<Vec<u8>, String> {
+    let bytes = decode(secret)?;
+    if bytes.len() != SECRET_LEN {
+        return Err("Invalid room secret".to_string());
+    }
+    Ok(bytes)
+}
+
+fn decode(encoded: &str) -> Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD.decode(encoded).map_err(|e| e.to_string())
+}
+
+fn subtle() -> Result<SubtleCrypto, String> {
+    Ok(window().crypto().map_err(js_error)?.subtle())
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0; N];
+    window()
+        .crypto()
+        .map_err(js_error)?
+        .get_random_values_with_u8_array(&mut bytes)
+        .map_err(js_error)?;
+    Ok(bytes)
+}
+
+fn usages(usages: &[&str]) -> JsValue {
+    usages
+        .iter()
+        .map(|usage| JsValue::from_str(usage))
+        .collect::<Array>()
+        .into()
+}
+
+async fn resolve(promise: Result<Promise, JsValue>) -> Result<JsValue, String> {
+    JsFuture::from(promise.map_err(js_error)?)
+        .await
+        .map_err(js_error)
+}
+
+fn js_error(e: JsValue) -> String {
+    format!("{:?}", e)
+}
+
+// WebCrypto only exists in the browser: run with `wasm-pack test --headless --firefox`
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const ROOM: &str = "room-1";
+
+    async fn members(count: usize) -> Vec<DeviceIdentity> {
+        let mut devices = Vec::new();
+        for _ in 0..count {
+            devices.push(DeviceIdentity::generate().await.unwrap());
+        }
+        devices
+    }
+
+    /// Unwraps `key_epoch` for `identity`, trusting only `signer`.
+    async fn room_key(
+        identity: &DeviceIdentity,
+        key_epoch: &KeyEpoch,
+        signer: &DeviceIdentity,
+    ) -> Result<RoomKey, String> {
+        let trusted = signer.fingerprint().await.unwrap();
+        let secret = unwrap_room_secret(ROOM, identity, key_epoch, &trusted).await?;
+        RoomKey::derive(ROOM, key_epoch.epoch, &secret).await
+    }
+
+    #[wasm_bindgen_test]
+    async fn members_decrypt_messages_after_rotation() {
+        let devices = members(2).await;
+        let public: Vec<_> = devices.iter().map(DeviceIdentity::public).collect();
+        let (secret, key_epoch) = rotate_room_secret(ROOM, 1, &devices[0], &public)
+            .await
+            .unwrap();
+
+        let sender = RoomKey::derive(ROOM, 1, &secret).await.unwrap();
+        let envelope = sender.encrypt("incident update").await.unwrap();
+        assert_eq!(envelope_epoch(&envelope), Some(1));
+
+        for device in &devices {
+            let key = room_key(device, &key_epoch, &devices[0]).await.unwrap();
+            assert_eq!(key.decrypt(&envelope).await.unwrap(), "incident update");
+        }
+    }
+
+    #[wasm_bindgen_test]
+    async fn removed_member_cannot_decrypt_new_messages() {
+        let devices = members(3).await;
+        let (alice, bob, mallory) = (&devices[0], &devices[1], &devices[2]);
+
+        let everyone: Vec<_> = devices.iter().map(DeviceIdentity::public).collect();
+        let (_, first) = rotate_room_secret(ROOM, 1, alice, &everyone).await.unwrap();
+        let mallory_old_key = room_key(mallory, &first, alice).await.unwrap();
+
+        // Mallory is removed, so the next epoch is only wrapped for Alice and Bob
+        let remaining = [alice.public(), bob.public()];
+        let (secret, second) = rotate_room_secret(ROOM, 2, alice, &remaining)
+            .await
+            .unwrap();
+        let envelope = RoomKey::derive(ROOM, 2, &secret)
+            .await
+            .unwrap()
+            .encrypt("after the kick")
+            .await
+            .unwrap();
+
+        assert!(room_key(mallory, &second, alice).await.is_err());
+        assert!(mallory_old_key.decrypt(&envelope).await.is_err());
+        let bob_key = room_key(bob, &second, alice).await.unwrap();
+        assert_eq!(bob_key.decrypt(&envelope).await.unwrap(), "after the kick");
+    }
+
+    #[wasm_bindgen_test]
+    async fn removed_member_cannot_use_another_members_wrapped_key() {
+        let devices = members(3).await;
+        let (alice, bob, mallory) = (&devices[0], &devices[1], &devices[2]);
+
+        let (_, mut key_epoch) =
+            rotate_room_secret(ROOM, 2, alice, &[alice.public(), bob.public()])
+                .await
+                .unwrap();
+        // Relabelling Bob's copy doesn't help, even re-signed: it is bound to Bob's private key
+        for wrapped in &mut key_epoch.wrapped {
+            wrapped.device = mallory.id.clone();
+        }
+        key_epoch.signer = mallory.public();
+        sign_key_epoch(ROOM, mallory, &mut key_epoch).await.unwrap();
+
+        assert!(room_key(mallory, &key_epoch, mallory).await.is_err());
+    }
+
+    #[wasm_bindgen_test]
+    async fn rejects_unsigned_and_tampered_epochs() {
+        let devices = members(3).await;
+        let (alice, bob, mallory) = (&devices[0], &devices[1], &devices[2]);
+        let (_, key_epoch) = rotate_room_secret(ROOM, 1, alice, &[alice.public(), bob.public()])
+            .await
+            .unwrap();
+        assert!(room_key(bob, &key_epoch, alice).await.is_ok());
+
+        let mut unsigned = key_epoch.clone();
+        unsigned.signature.clear();
+        assert!(room_key(bob, &unsigned, alice).await.is_err());
+
+        // The relay swaps in a key of its own for Bob
+        let (_, forged) = rotate_room_secret(ROOM, 1, mallory, &[bob.public()])
+            .await
+            .unwrap();
+        let mut tampered = key_epoch.clone();
+        tampered.wrapped[1] = forged.wrapped[0].clone();
+        assert!(room_key(bob, &tampered, alice).await.is_err());
+
+        // Signed properly, but by a device Bob hasn't verified
+        assert!(room_key(bob, &forged, alice).await.is_err());
+
+        // A valid epoch can't be replayed into another room
+        let trusted = alice.fingerprint().await.unwrap();
+        assert!(unwrap_room_secret("room-2", bob, &key_epoch, &trusted)
+            .await
+            .is_err());
+    }
+
+    #[wasm_bindgen_test]
+    async fn envelopes_are_bound_to_their_room() {
+        let secret = generate_room_secret().unwrap();
+        let envelope = RoomKey::derive(ROOM, 0, &secret)
+            .await
+            .unwrap()
+            .encrypt("hello")
+            .await
+            .unwrap();
+
+        let other_room = RoomKey::derive("room-2", 0, &secret).await.unwrap();
+        assert!(other_room.decrypt(&envelope).await.is_err());
+    }
+}
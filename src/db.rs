@@ -1,5 +1,9 @@
-use rexie::{Error, ObjectStore, Rexie, TransactionMode};
+use crate::crypto::DeviceIdentity;
+use rexie::Rexie;
+#[cfg(not(feature = "ssr"))]
+use rexie::{ObjectStore, TransactionMode};
 use serde::{Deserialize, Serialize};
+#[cfg(not(feature = "ssr"))]
 use wasm_bindgen::JsValue;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -8,6 +12,52 @@ pub struct User {
     pub email: String,
     pub phone: String,
     pub created_at: u64,
+    /// Id of this browser's identity key pair in the `identity_keys` store.
+    #[serde(default)]
+    pub device_key: Option<String>,
+}
+
+/// How this browser came to know a room, shown as a hint in the header. It is decided locally
+/// and grants nothing: there is no server-side membership to enforce roles against.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinedAs {
+    /// Created the room here.
+    #[serde(alias = "Owner")]
+    Creator,
+    /// Joined with an invite code or link.
+    #[default]
+    #[serde(alias = "Member", alias = "Moderator")]
+    Invitee,
+}
+
+/// A room the local user has joined, remembered so it can be listed on the home page.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RoomEntry {
+    pub code: String,
+    pub name: String,
+    // Records from older versions also carry `last_message` and `unread`, which are ignored
+    #[serde(default, alias = "role")]
+    pub joined_as: JoinedAs,
+    pub joined_at: u64,
+    pub last_activity: u64,
+    /// Secret of an end-to-end encrypted room, taken from its invite link fragment.
+    /// It only ever lives in this browser.
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+impl RoomEntry {
+    pub fn new(code: String, joined_as: JoinedAs, secret: Option<String>, now: u64) -> Self {
+        let name = format!("Room {}", code.chars().take(8).collect::<String>());
+        Self {
+            code,
+            name,
+            joined_as,
+            joined_at: now,
+            last_activity: now,
+            secret,
+        }
+    }
 }
 
 #[cfg(feature = "ssr")]
@@ -25,11 +75,37 @@ pub async fn get_user() -> Result<Option<User>, String> {
     Ok(None)
 }
 
+#[cfg(feature = "ssr")]
+pub async fn device_identity() -> Result<DeviceIdentity, String> {
+    Err("WebCrypto not available on server".to_string())
+}
+
+#[cfg(feature = "ssr")]
+pub async fn touch_room(
+    code: String,
+    joined_as: JoinedAs,
+    secret: Option<String>,
+) -> Result<RoomEntry, String> {
+    Ok(RoomEntry::new(code, joined_as, secret, 0))
+}
+
+#[cfg(feature = "ssr")]
+pub async fn forget_room(_code: String) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(feature = "ssr")]
+pub async fn get_rooms() -> Result<Vec<RoomEntry>, String> {
+    Ok(Vec::new())
+}
+
 #[cfg(not(feature = "ssr"))]
 pub async fn init_db() -> Result<Rexie, String> {
     let rexie = Rexie::builder("chat_stream_db")
-        .version(1)
+        .version(3)
         .add_object_store(ObjectStore::new("users").auto_increment(true))
+        .add_object_store(ObjectStore::new("rooms").key_path("code"))
+        .add_object_store(ObjectStore::new("identity_keys").key_path("id"))
         .build()
         .await
         .map_err(|e| e.to_string())?;
@@ -37,8 +113,13 @@ pub async fn init_db() -> Result<Rexie, String> {
     Ok(rexie)
 }
 
+/// Replaces the stored profile. A profile saved without a device key keeps the existing one.
 #[cfg(not(feature = "ssr"))]
-pub async fn save_user(user: User) -> Result<(), String> {
+pub async fn save_user(mut user: User) -> Result<(), String> {
+    if user.device_key.is_none() {
+        user.device_key = get_user().await?.and_then(|existing| existing.device_key);
+    }
+
     let rexie = init_db().await?;
     let transaction = rexie
         .transaction(&["users"], TransactionMode::ReadWrite)
@@ -77,9 +158,177 @@ pub async fn get_user() -> Result<Option<User>, String> {
     }
 
     // user_pair is actually just the JsValue, not a pair since get_all returns values
-    let user_js = users.get(0).ok_or("No user found".to_string())?;
+    let user_js = users.first().ok_or("No user found".to_string())?;
     let user: User = serde_wasm_bindgen::from_value(user_js.clone())
         .map_err(|e| format!("Deserialization error: {}", e))?;
 
     Ok(Some(user))
 }
+
+/// Loads this browser's identity key pair, generating it on first use and recording its id in
+/// the user profile. An identity is only stored once there is a profile to link it to, so none
+/// are left behind that nothing points at.
+#[cfg(not(feature = "ssr"))]
+pub async fn device_identity() -> Result<DeviceIdentity, String> {
+    let mut user = get_user()
+        .await?
+        .ok_or("Save a profile before creating a device identity")?;
+    let previous = user.device_key.clone();
+    if let Some(id) = &previous {
+        let rexie = init_db().await?;
+        let transaction = rexie
+            .transaction(&["identity_keys"], TransactionMode::ReadOnly)
+            .map_err(|e| e.to_string())?;
+        let keys_store = transaction
+            .store("identity_keys")
+            .map_err(|e| e.to_string())?;
+        let record = keys_store
+            .get(JsValue::from_str(id))
+            .await
+            .map_err(|e| e.to_string())?;
+        // Identities stored before epochs were signed have no signing key; they are replaced
+        if let Some(Ok(identity)) = record.map(|record| DeviceIdentity::from_js_value(&record)) {
+            return Ok(identity);
+        }
+    }
+
+    // Generated outside any transaction, which would otherwise commit while WebCrypto runs
+    let identity = DeviceIdentity::generate().await?;
+
+    let rexie = init_db().await?;
+    let transaction = rexie
+        .transaction(&["identity_keys"], TransactionMode::ReadWrite)
+        .map_err(|e| e.to_string())?;
+    let keys_store = transaction
+        .store("identity_keys")
+        .map_err(|e| e.to_string())?;
+    keys_store
+        .put(&identity.to_js_value(), None)
+        .await
+        .map_err(|e| e.to_string())?;
+    transaction.done().await.map_err(|e| e.to_string())?;
+
+    user.device_key = Some(identity.id.clone());
+    save_user(user).await?;
+
+    // Only once the profile points at the new identity, so a failure never leaves it dangling
+    if let Some(id) = previous {
+        let transaction = rexie
+            .transaction(&["identity_keys"], TransactionMode::ReadWrite)
+            .map_err(|e| e.to_string())?;
+        let keys_store = transaction
+            .store("identity_keys")
+            .map_err(|e| e.to_string())?;
+        keys_store
+            .delete(JsValue::from_str(&id))
+            .await
+            .map_err(|e| e.to_string())?;
+        transaction.done().await.map_err(|e| e.to_string())?;
+    }
+
+    Ok(identity)
+}
+
+/// Records that the user is in `code`, creating the entry as `joined_as` on first join.
+/// A room's `secret` is kept from the first link that carried one.
+#[cfg(not(feature = "ssr"))]
+pub async fn touch_room(
+    code: String,
+    joined_as: JoinedAs,
+    secret: Option<String>,
+) -> Result<RoomEntry, String> {
+    let rexie = init_db().await?;
+    let transaction = rexie
+        .transaction(&["rooms"], TransactionMode::ReadWrite)
+        .map_err(|e| e.to_string())?;
+    let rooms_store = transaction.store("rooms").map_err(|e| e.to_string())?;
+
+    let now = js_sys::Date::now() as u64;
+    let existing = rooms_store
+        .get(JsValue::from_str(&code))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let room = match existing {
+        Some(room_js) => {
+            let mut room: RoomEntry = serde_wasm_bindgen::from_value(room_js)
+                .map_err(|e| format!("Deserialization error: {}", e))?;
+            room.last_activity = now;
+            if room.secret.is_none() {
+                room.secret = secret;
+            }
+            room
+        }
+        None => RoomEntry::new(code, joined_as, secret, now),
+    };
+
+    let room_js_value =
+        serde_wasm_bindgen::to_value(&room).map_err(|e| format!("Serialization error: {}", e))?;
+
+    rooms_store
+        .put(&room_js_value, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    transaction.done().await.map_err(|e| e.to_string())?;
+
+    Ok(room)
+}
+
+/// Returns the joined rooms, most recently active first.
+#[cfg(not(feature = "ssr"))]
+pub async fn get_rooms() -> Result<Vec<RoomEntry>, String> {
+    let rexie = init_db().await?;
+    let transaction = rexie
+        .transaction(&["rooms"], TransactionMode::ReadOnly)
+        .map_err(|e| e.to_string())?;
+    let rooms_store = transaction.store("rooms").map_err(|e| e.to_string())?;
+
+    let rooms_js = rooms_store
+        .get_all(None, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut rooms = rooms_js
+        .into_iter()
+        .map(|room_js| {
+            serde_wasm_bindgen::from_value::<RoomEntry>(room_js)
+                .map_err(|e| format!("Deserialization error: {}", e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    rooms.sort_by_key(|room| std::cmp::Reverse(room.last_activity));
+
+    Ok(rooms)
+}
+
+/// Removes `code` from the joined rooms.
+#[cfg(not(feature = "ssr"))]
+pub async fn forget_room(code: String) -> Result<(), String> {
+    let rexie = init_db().await?;
+    let transaction = rexie
+        .transaction(&["rooms"], TransactionMode::ReadWrite)
+        .map_err(|e| e.to_string())?;
+    let rooms_store = transaction.store("rooms").map_err(|e| e.to_string())?;
+
+    rooms_store
+        .delete(JsValue::from_str(&code))
+        .await
+        .map_err(|e| e.to_string())?;
+    transaction.done().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_room_records_from_older_versions() {
+        let room: RoomEntry = leptos::serde_json::from_str(
+            r#"{"code":"abc","name":"Room abc","last_message":"hi","unread":3,"role":"Owner","joined_at":1,"last_activity":2}"#,
+        )
+        .unwrap();
+        assert_eq!(room.joined_as, JoinedAs::Creator);
+        assert_eq!(room.secret, None);
+    }
+}
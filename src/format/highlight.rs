@@ -0,0 +1,374 @@
+/// The kind of a highlighted token, mapped to a colour when rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Plain,
+    Keyword,
+    Literal,
+    String,
+    Number,
+    Comment,
+}
+
+impl TokenKind {
+    pub fn class(self) -> &'static str {
+        match self {
+            TokenKind::Plain => "",
+            TokenKind::Keyword => "text-purple-400",
+            TokenKind::Literal => "text-orange-300",
+            TokenKind::String => "text-green-300",
+            TokenKind::Number => "text-amber-300",
+            TokenKind::Comment => "text-gray-500 italic",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+}
+
+/// The lexical rules for one supported language.
+pub struct Language {
+    keywords: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    case_insensitive: bool,
+}
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "type",
+        "unsafe", "use", "where", "while",
+    ],
+    literals: &["true", "false", "None", "Some", "Ok", "Err"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    // Single quotes are left alone so lifetimes don't open a string
+    quotes: &['"'],
+    case_insensitive: false,
+};
+
+const TYPESCRIPT: Language = Language {
+    keywords: &[
+        "abstract",
+        "any",
+        "as",
+        "async",
+        "await",
+        "boolean",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "constructor",
+        "continue",
+        "declare",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "enum",
+        "export",
+        "extends",
+        "finally",
+        "for",
+        "from",
+        "function",
+        "get",
+        "if",
+        "implements",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "number",
+        "of",
+        "private",
+        "protected",
+        "public",
+        "readonly",
+        "return",
+        "set",
+        "static",
+        "string",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "try",
+        "type",
+        "typeof",
+        "var",
+        "void",
+        "while",
+        "yield",
+    ],
+    literals: &["true", "false", "null", "undefined"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    case_insensitive: false,
+};
+
+const JSON: Language = Language {
+    keywords: &[],
+    literals: &["true", "false", "null"],
+    line_comments: &[],
+    block_comment: None,
+    quotes: &['"'],
+    case_insensitive: false,
+};
+
+const SQL: Language = Language {
+    keywords: &[
+        "select",
+        "from",
+        "where",
+        "insert",
+        "into",
+        "values",
+        "update",
+        "set",
+        "delete",
+        "create",
+        "table",
+        "drop",
+        "alter",
+        "index",
+        "join",
+        "inner",
+        "left",
+        "right",
+        "outer",
+        "on",
+        "and",
+        "or",
+        "not",
+        "is",
+        "in",
+        "as",
+        "order",
+        "by",
+        "group",
+        "having",
+        "limit",
+        "offset",
+        "distinct",
+        "primary",
+        "key",
+        "foreign",
+        "references",
+        "union",
+        "all",
+        "exists",
+        "case",
+        "when",
+        "then",
+        "else",
+        "end",
+        "default",
+        "begin",
+        "commit",
+        "rollback",
+    ],
+    literals: &["true", "false", "null"],
+    line_comments: &["--"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['\'', '"'],
+    case_insensitive: true,
+};
+
+const SHELL: Language = Language {
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+        "in", "function", "return", "export", "local", "echo", "cd", "exit", "sudo",
+    ],
+    literals: &["true", "false"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    case_insensitive: false,
+};
+
+/// Looks up a language by its fenced code block tag.
+pub fn language(tag: &str) -> Option<&'static Language> {
+    match tag {
+        "rust" | "rs" => Some(&RUST),
+        "typescript" | "ts" | "tsx" | "javascript" | "js" | "jsx" => Some(&TYPESCRIPT),
+        "json" => Some(&JSON),
+        "sql" => Some(&SQL),
+        "shell" | "sh" | "bash" | "zsh" | "console" => Some(&SHELL),
+        _ => None,
+    }
+}
+
+/// Splits `code` into highlighted tokens. Concatenating the token texts gives back `code`.
+pub fn highlight(lang: &Language, code: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut rest = code;
+
+    while let Some(c) = rest.chars().next() {
+        let (kind, len) = next_token(lang, rest, prev).unwrap_or((TokenKind::Plain, c.len_utf8()));
+        let text = &rest[..len];
+
+        match tokens.last_mut() {
+            Some(last) if last.kind == kind && kind == TokenKind::Plain => last.text.push_str(text),
+            _ => tokens.push(Token {
+                kind,
+                text: text.to_string(),
+            }),
+        }
+        prev = text.chars().last();
+        rest = &rest[len..];
+    }
+
+    tokens
+}
+
+/// Classifies the token at the start of `rest`, returning its kind and length in bytes.
+fn next_token(lang: &Language, rest: &str, prev: Option<char>) -> Option<(TokenKind, usize)> {
+    let at_boundary = !prev.is_some_and(is_ident_char);
+
+    if let Some((open, close)) = lang.block_comment {
+        if let Some(body) = rest.strip_prefix(open) {
+            let len = body
+                .find(close)
+                .map_or(rest.len(), |end| open.len() + end + close.len());
+            return Some((TokenKind::Comment, len));
+        }
+    }
+    if at_boundary
+        && lang
+            .line_comments
+            .iter()
+            .any(|marker| rest.starts_with(marker))
+    {
+        return Some((TokenKind::Comment, rest.find('\n').unwrap_or(rest.len())));
+    }
+
+    let c = rest.chars().next()?;
+    if lang.quotes.contains(&c) {
+        return Some((TokenKind::String, string_len(rest, c)));
+    }
+    if at_boundary && c.is_ascii_digit() {
+        let len = rest
+            .find(|c: char| !(is_ident_char(c) || c == '.'))
+            .unwrap_or(rest.len());
+        return Some((TokenKind::Number, len));
+    }
+    if at_boundary && is_ident_char(c) {
+        let len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+        let word = &rest[..len];
+        let is_in = |words: &[&str]| {
+            words.iter().any(|w| {
+                if lang.case_insensitive {
+                    w.eq_ignore_ascii_case(word)
+                } else {
+                    *w == word
+                }
+            })
+        };
+        let kind = if is_in(lang.keywords) {
+            TokenKind::Keyword
+        } else if is_in(lang.literals) {
+            TokenKind::Literal
+        } else {
+            TokenKind::Plain
+        };
+        return Some((kind, len));
+    }
+
+    None
+}
+
+/// Length of a quoted string starting at `rest`, honouring backslash escapes. Unterminated strings run to the end.
+fn string_len(rest: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return i + c.len_utf8();
+        }
+    }
+    rest.len()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(lang: &str, code: &str) -> Vec<(TokenKind, String)> {
+        highlight(language(lang).unwrap(), code)
+            .into_iter()
+            .filter(|t| t.kind != TokenKind::Plain)
+            .map(|t| (t.kind, t.text))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let code = "fn main() {\n    // hi\n    let s = \"a\\\"b\";\n}";
+        let tokens = highlight(language("rust").unwrap(), code);
+        assert_eq!(
+            tokens.iter().map(|t| t.text.as_str()).collect::<String>(),
+            code
+        );
+    }
+
+    #[test]
+    fn highlights_rust() {
+        assert_eq!(
+            kinds("rust", "let x: &'a str = \"hi\"; // done"),
+            vec![
+                (TokenKind::Keyword, "let".to_string()),
+                (TokenKind::String, "\"hi\"".to_string()),
+                (TokenKind::Comment, "// done".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn sql_keywords_ignore_case() {
+        assert_eq!(
+            kinds("sql", "SELECT id FROM users WHERE n = 1"),
+            vec![
+                (TokenKind::Keyword, "SELECT".to_string()),
+                (TokenKind::Keyword, "FROM".to_string()),
+                (TokenKind::Keyword, "WHERE".to_string()),
+                (TokenKind::Number, "1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shell_hash_inside_word_is_not_a_comment() {
+        assert_eq!(
+            kinds("bash", "echo a#b # note"),
+            vec![
+                (TokenKind::Keyword, "echo".to_string()),
+                (TokenKind::Comment, "# note".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_languages_are_not_supported() {
+        assert!(language("brainfuck").is_none());
+    }
+}
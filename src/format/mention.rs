@@ -0,0 +1,89 @@
+use super::{parse, Block, Inline};
+
+/// The handle used to mention a user: their display name without whitespace.
+pub fn mention_handle(name: &str) -> String {
+    name.split_whitespace().collect()
+}
+
+/// Parses a mention at the start of `rest`, returning the handle and the bytes consumed.
+pub fn parse_mention(rest: &str) -> Option<(&str, usize)> {
+    let body = rest.strip_prefix('@')?;
+    let end = body
+        .find(|c: char| !is_handle_char(c))
+        .unwrap_or(body.len());
+    let handle = body[..end].trim_end_matches(['.', '-']);
+    (!handle.is_empty()).then(|| (handle, 1 + handle.len()))
+}
+
+/// Whether an `@` after `prev` can start a mention. Anything that could belong to a handle or
+/// an email's local part (`a_@b`, `bob@example.com`) rules it out. Shared with the renderer so
+/// what is highlighted is exactly what gets stored.
+pub fn can_start_mention(prev: Option<char>) -> bool {
+    !prev.is_some_and(is_handle_char)
+}
+
+/// Finds the handle of every mention in `text`, read off the parsed message so that exactly
+/// what the renderer highlights counts: not `@` inside code, links or email addresses.
+pub fn extract_mentions(text: &str) -> Vec<String> {
+    fn collect(inlines: &[Inline], out: &mut Vec<String>) {
+        for inline in inlines {
+            match inline {
+                Inline::Mention(handle) => out.push(handle.clone()),
+                Inline::Bold(children) | Inline::Italic(children) => collect(children, out),
+                Inline::Text(_) | Inline::Code(_) | Inline::Link { .. } => {}
+            }
+        }
+    }
+
+    let mut handles = Vec::new();
+    for block in parse(text) {
+        match block {
+            Block::Paragraph(inlines) => collect(&inlines, &mut handles),
+            Block::List { items, .. } => {
+                for item in &items {
+                    collect(item, &mut handles);
+                }
+            }
+            Block::CodeBlock { .. } => {}
+        }
+    }
+    handles
+}
+
+/// Whether `text` mentions the user called `name`.
+pub fn mentions_user(text: &str, name: &str) -> bool {
+    let handle = mention_handle(name);
+    !handle.is_empty()
+        && extract_mentions(text)
+            .iter()
+            .any(|mention| mention.eq_ignore_ascii_case(&handle))
+}
+
+fn is_handle_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_mentions() {
+        assert_eq!(
+            extract_mentions("hey @JohnDoe, ping @ana."),
+            vec!["JohnDoe".to_string(), "ana".to_string()]
+        );
+    }
+
+    #[test]
+    fn emails_are_not_mentions() {
+        assert!(extract_mentions("mail john@example.com").is_empty());
+        assert!(extract_mentions("a lone @ sign").is_empty());
+    }
+
+    #[test]
+    fn matches_display_names() {
+        assert!(mentions_user("thanks @johndoe!", "John Doe"));
+        assert!(!mentions_user("thanks @johndoe!", "Jane"));
+    }
+}
@@ -0,0 +1,542 @@
+pub mod highlight;
+pub mod mention;
+
+use leptos::prelude::*;
+
+/// A block-level piece of a formatted message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Inline>),
+    CodeBlock {
+        lang: Option<String>,
+        code: String,
+    },
+    List {
+        ordered: bool,
+        items: Vec<Vec<Inline>>,
+    },
+}
+
+/// An inline span within a paragraph or list item.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    Code(String),
+    Link { text: String, href: String },
+    Mention(String),
+}
+
+/// Parses the supported markdown subset. Anything not recognised (including HTML) stays plain text.
+pub fn parse(input: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut lines = input.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+
+        if let Some(lang) = trimmed.strip_prefix("```") {
+            flush_paragraph(&mut blocks, &mut paragraph);
+            // A fence closed on the same line is inline-sized code with no language
+            if let Some(end) = lang.find("```") {
+                blocks.push(Block::CodeBlock {
+                    lang: None,
+                    code: lang[..end].to_string(),
+                });
+                let tail = lang[end + 3..].trim();
+                if !tail.is_empty() {
+                    paragraph.push(tail);
+                }
+                continue;
+            }
+            let mut code = Vec::new();
+            for line in lines.by_ref() {
+                if line.trim_start().starts_with("```") {
+                    break;
+                }
+                code.push(line);
+            }
+            blocks.push(Block::CodeBlock {
+                lang: parse_lang(lang),
+                code: code.join("\n"),
+            });
+        } else if let Some((ordered, item)) = list_item(trimmed) {
+            flush_paragraph(&mut blocks, &mut paragraph);
+            match blocks.last_mut() {
+                Some(Block::List { ordered: o, items }) if *o == ordered => {
+                    items.push(parse_inline(item))
+                }
+                _ => blocks.push(Block::List {
+                    ordered,
+                    items: vec![parse_inline(item)],
+                }),
+            }
+        } else if trimmed.is_empty() {
+            flush_paragraph(&mut blocks, &mut paragraph);
+        } else {
+            paragraph.push(line);
+        }
+    }
+    flush_paragraph(&mut blocks, &mut paragraph);
+
+    blocks
+}
+
+fn flush_paragraph(blocks: &mut Vec<Block>, paragraph: &mut Vec<&str>) {
+    if !paragraph.is_empty() {
+        blocks.push(Block::Paragraph(parse_inline(&paragraph.join("\n"))));
+        paragraph.clear();
+    }
+}
+
+fn parse_lang(lang: &str) -> Option<String> {
+    let lang = lang.trim();
+    let valid = !lang.is_empty()
+        && lang
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '#' | '_'));
+    valid.then(|| lang.to_ascii_lowercase())
+}
+
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    if let Some(item) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+        return Some((false, item));
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        if let Some(item) = line[digits..].strip_prefix(". ") {
+            return Some((true, item));
+        }
+    }
+    None
+}
+
+/// Parses inline spans: `**bold**`, `*italic*`/`_italic_`, `` `code` ``, `[text](url)`, `@mentions` and bare URLs.
+pub fn parse_inline(text: &str) -> Vec<Inline> {
+    let mut spans = Vec::new();
+    let mut buf = String::new();
+    let mut prev: Option<char> = None;
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some((span, consumed)) = parse_span(rest, prev) {
+            if !buf.is_empty() {
+                spans.push(Inline::Text(std::mem::take(&mut buf)));
+            }
+            spans.push(span);
+            prev = rest[..consumed].chars().last();
+            rest = &rest[consumed..];
+            continue;
+        }
+        buf.push(c);
+        prev = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !buf.is_empty() {
+        spans.push(Inline::Text(buf));
+    }
+
+    spans
+}
+
+/// Tries to parse a span at the start of `rest`, returning it with the number of bytes consumed.
+fn parse_span(rest: &str, prev: Option<char>) -> Option<(Inline, usize)> {
+    let at_boundary = !prev.is_some_and(|c| c.is_alphanumeric());
+
+    if let Some(body) = rest.strip_prefix('`') {
+        let end = body.find('`').filter(|&end| end > 0)?;
+        return Some((Inline::Code(body[..end].to_string()), end + 2));
+    }
+    if let Some(body) = rest.strip_prefix("**") {
+        let end = body.find("**").filter(|&end| end > 0)?;
+        return Some((Inline::Bold(parse_inline(&body[..end])), end + 4));
+    }
+    if let Some(body) = rest.strip_prefix('*') {
+        let end = body.find('*').filter(|&end| end > 0)?;
+        if body.starts_with(char::is_whitespace) {
+            return None;
+        }
+        return Some((Inline::Italic(parse_inline(&body[..end])), end + 2));
+    }
+    if let Some(body) = rest.strip_prefix('_').filter(|_| at_boundary) {
+        let end = body.find('_').filter(|&end| end > 0)?;
+        if body[end + 1..].starts_with(char::is_alphanumeric) {
+            return None;
+        }
+        return Some((Inline::Italic(parse_inline(&body[..end])), end + 2));
+    }
+    if let Some(body) = rest.strip_prefix('[') {
+        let label_end = body.find("](")?;
+        let target = &body[label_end + 2..];
+        let href_end = target.find(')')?;
+        let href = target[..href_end].trim();
+        if !is_safe_href(href) {
+            return None;
+        }
+        let link = Inline::Link {
+            text: body[..label_end].to_string(),
+            href: href.to_string(),
+        };
+        return Some((link, 1 + label_end + 2 + href_end + 1));
+    }
+    if rest.starts_with('@') && mention::can_start_mention(prev) {
+        let (handle, len) = mention::parse_mention(rest)?;
+        return Some((Inline::Mention(handle.to_string()), len));
+    }
+    if at_boundary && (rest.starts_with("http://") || rest.starts_with("https://")) {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let url = rest[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'', '"']);
+        if !is_safe_href(url) {
+            return None;
+        }
+        let link = Inline::Link {
+            text: url.to_string(),
+            href: url.to_string(),
+        };
+        return Some((link, url.len()));
+    }
+
+    None
+}
+
+/// Only plain web and mail links are allowed; `javascript:`, `data:` and friends never become links.
+pub fn is_safe_href(href: &str) -> bool {
+    if href.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return false;
+    }
+    let lower = href.to_ascii_lowercase();
+    let rest = ["https://", "http://", "mailto:"]
+        .iter()
+        .find_map(|scheme| lower.strip_prefix(scheme));
+    rest.is_some_and(|rest| !rest.is_empty())
+}
+
+/// Renders a message body with the supported markdown subset.
+///
+/// Every piece of text goes through Leptos as a text node or escaped attribute, never as raw HTML.
+#[component]
+pub fn FormattedMessage(#[prop(into)] text: String) -> impl IntoView {
+    parse(&text).into_iter().map(render_block).collect_view()
+}
+
+fn render_block(block: Block) -> AnyView {
+    match block {
+        Block::Paragraph(inlines) => view! {
+            <p class="whitespace-pre-wrap break-words">{render_inlines(inlines)}</p>
+        }
+        .into_any(),
+        Block::CodeBlock { lang, code } => view! { <CodeBlock lang=lang code=code/> }.into_any(),
+        Block::List {
+            ordered: false,
+            items,
+        } => view! {
+            <ul class="list-disc pl-6">{render_items(items)}</ul>
+        }
+        .into_any(),
+        Block::List {
+            ordered: true,
+            items,
+        } => view! {
+            <ol class="list-decimal pl-6">{render_items(items)}</ol>
+        }
+        .into_any(),
+    }
+}
+
+/// A fenced code block, syntax-highlighted when its language is known, with a copy button.
+#[component]
+fn CodeBlock(lang: Option<String>, code: String) -> impl IntoView {
+    let highlighted = match lang.as_deref().and_then(highlight::language) {
+        Some(language) => highlight::highlight(language, &code)
+            .into_iter()
+            .map(|token| view! { <span class=token.kind.class()>{token.text}</span> }.into_any())
+            .collect::<Vec<_>>(),
+        None => vec![code.clone().into_any()],
+    };
+    let (copied, set_copied) = signal(false);
+
+    let on_copy = move |_| {
+        let _ = window().navigator().clipboard().write_text(&code);
+        set_copied.set(true);
+    };
+
+    view! {
+        <div class="relative my-2 group">
+            <button
+                type="button"
+                class="absolute top-2 right-2 px-2 py-1 text-xs font-semibold text-gray-300 bg-gray-800 rounded-lg opacity-0 group-hover:opacity-100 focus-visible:opacity-100 hover:text-white transition-all"
+                on:click=on_copy
+            >
+                {move || if copied.get() { "Copied" } else { "Copy" }}
+            </button>
+            <pre class="p-4 rounded-xl bg-gray-900 text-gray-100 overflow-x-auto text-left">
+                <code class=format!("font-mono text-sm language-{}", lang.as_deref().unwrap_or("text"))>
+                    {highlighted}
+                </code>
+            </pre>
+        </div>
+    }
+}
+
+fn render_items(items: Vec<Vec<Inline>>) -> Vec<AnyView> {
+    items
+        .into_iter()
+        .map(|item| view! { <li>{render_inlines(item)}</li> }.into_any())
+        .collect()
+}
+
+fn render_inlines(inlines: Vec<Inline>) -> Vec<AnyView> {
+    inlines.into_iter().map(render_inline).collect()
+}
+
+fn render_inline(inline: Inline) -> AnyView {
+    match inline {
+        Inline::Text(text) => text.into_any(),
+        Inline::Bold(children) => view! { <strong>{render_inlines(children)}</strong> }.into_any(),
+        Inline::Italic(children) => view! { <em>{render_inlines(children)}</em> }.into_any(),
+        Inline::Code(code) => view! {
+            <code class="px-1 py-0.5 rounded bg-gray-100 font-mono text-sm">{code}</code>
+        }
+        .into_any(),
+        Inline::Link { text, href } => view! {
+            <a href=href target="_blank" rel="noopener noreferrer nofollow" class="text-blue-600 underline">
+                {text}
+            </a>
+        }
+        .into_any(),
+        Inline::Mention(handle) => view! {
+            <span class="px-1 rounded bg-blue-100 text-blue-700 font-semibold">"@"{handle}</span>
+        }
+        .into_any(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Inline {
+        Inline::Text(s.to_string())
+    }
+
+    #[test]
+    fn parses_inline_styles() {
+        assert_eq!(
+            parse_inline("**bold** and *italic* and `code`"),
+            vec![
+                Inline::Bold(vec![text("bold")]),
+                text(" and "),
+                Inline::Italic(vec![text("italic")]),
+                text(" and "),
+                Inline::Code("code".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn underscores_inside_words_are_not_italic() {
+        assert_eq!(
+            parse_inline("snake_case_name"),
+            vec![text("snake_case_name")]
+        );
+        assert_eq!(
+            parse_inline("an _emphasis_."),
+            vec![
+                text("an "),
+                Inline::Italic(vec![text("emphasis")]),
+                text(".")
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_fenced_code_blocks() {
+        assert_eq!(
+            parse("before\n```rust\nfn main() {}\n**not bold**\n```\nafter"),
+            vec![
+                Block::Paragraph(vec![text("before")]),
+                Block::CodeBlock {
+                    lang: Some("rust".to_string()),
+                    code: "fn main() {}\n**not bold**".to_string(),
+                },
+                Block::Paragraph(vec![text("after")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn closes_fences_on_the_same_line() {
+        assert_eq!(
+            parse("```let x = 1;```\nafter"),
+            vec![
+                Block::CodeBlock {
+                    lang: None,
+                    code: "let x = 1;".to_string(),
+                },
+                Block::Paragraph(vec![text("after")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_lists() {
+        assert_eq!(
+            parse("- one\n- two\n1. first"),
+            vec![
+                Block::List {
+                    ordered: false,
+                    items: vec![vec![text("one")], vec![text("two")]],
+                },
+                Block::List {
+                    ordered: true,
+                    items: vec![vec![text("first")]],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_links() {
+        assert_eq!(
+            parse_inline("see [docs](https://leptos.dev) or https://example.com."),
+            vec![
+                text("see "),
+                Inline::Link {
+                    text: "docs".to_string(),
+                    href: "https://leptos.dev".to_string(),
+                },
+                text(" or "),
+                Inline::Link {
+                    text: "https://example.com".to_string(),
+                    href: "https://example.com".to_string(),
+                },
+                text("."),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_mentions() {
+        assert_eq!(
+            parse_inline("ping @ana or bob@example.com"),
+            vec![
+                text("ping "),
+                Inline::Mention("ana".to_string()),
+                text(" or bob@example.com"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rendered_mentions_match_extracted_ones() {
+        fn rendered(inlines: &[Inline], out: &mut Vec<String>) {
+            for inline in inlines {
+                match inline {
+                    Inline::Mention(handle) => out.push(handle.clone()),
+                    Inline::Bold(children) | Inline::Italic(children) => rendered(children, out),
+                    _ => {}
+                }
+            }
+        }
+
+        for message in [
+            "a_@bob and a.@carol and a-@dave",
+            "mail bob@example.com or @erin.",
+            "**@frank** (@grace), @heidi-",
+            "@ivan_@judy",
+            "`@bob` is code",
+            "see https://mastodon.social/@carol",
+            "[@dave](https://x.com) and @erin",
+        ] {
+            let mut highlighted = Vec::new();
+            rendered(&parse_inline(message), &mut highlighted);
+            assert_eq!(highlighted, mention::extract_mentions(message), "{message}");
+        }
+    }
+
+    #[test]
+    fn html_stays_text() {
+        let payload = "<script>alert(1)</script><img src=x onerror=alert(1)>";
+        assert_eq!(parse(payload), vec![Block::Paragraph(vec![text(payload)])]);
+    }
+
+    // Views only render to HTML with the server renderer
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn rendered_html_is_escaped() {
+        let message = "<script>alert(1)</script> **<img src=x onerror=alert(1)>**\n\
+                       ```html\n</code><script>alert(2)</script>\n```";
+        let html = Owner::new().with(|| view! { <FormattedMessage text=message/> }.to_html());
+        assert!(!html.contains("<script"), "{html}");
+        assert!(!html.contains("<img"), "{html}");
+        assert!(
+            html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"),
+            "{html}"
+        );
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn code_blocks_render_highlighted_tokens() {
+        let message = "```rust\nfn main() { let n = 42; } // done\n```";
+        let html = Owner::new().with(|| view! { <FormattedMessage text=message/> }.to_html());
+        assert!(
+            html.contains(r#"<div class="relative my-2 group">"#),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"<span class="text-purple-400">fn</span>"#),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"<span class="text-amber-300">42</span>"#),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"<span class="text-gray-500 italic">// done</span>"#),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"class="font-mono text-sm language-rust""#),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn rejects_script_links() {
+        for payload in [
+            "[x](javascript:alert(1))",
+            "[x](JaVaScRiPt:alert(1))",
+            "[x](data:text/html;base64,PHNjcmlwdD4=)",
+            "[x](vbscript:msgbox)",
+            "[x](java\tscript:alert(1))",
+            "[x](//evil.example)",
+        ] {
+            let spans = parse_inline(payload);
+            assert!(
+                !spans.iter().any(|span| matches!(span, Inline::Link { .. })),
+                "{payload} produced a link: {spans:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn quotes_in_links_stay_inside_the_href() {
+        let spans = parse_inline("[x](https://example.com/\"onmouseover=\"alert(1))");
+        assert_eq!(
+            spans[0],
+            Inline::Link {
+                text: "x".to_string(),
+                href: "https://example.com/\"onmouseover=\"alert(1".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn code_block_language_is_restricted() {
+        assert_eq!(parse_lang("rust"), Some("rust".to_string()));
+        assert_eq!(parse_lang("\"><script>"), None);
+    }
+}
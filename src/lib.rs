@@ -1,11 +1,18 @@
 pub mod app;
+pub mod attachment;
+pub mod crypto;
 pub mod db;
+pub mod format;
 pub mod pages;
+pub mod preview;
+#[cfg(feature = "ssr")]
+pub mod server;
+pub mod telemetry;
 
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
 pub fn hydrate() {
     use app::*;
-    console_error_panic_hook::set_once();
+    telemetry::install_error_reporting();
     leptos::mount::hydrate_body(App);
 }
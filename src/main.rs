@@ -4,29 +4,81 @@ async fn main() -> std::io::Result<()> {
     use actix_files::Files;
     use actix_web::*;
     use chat_stream::app::*;
+    use chat_stream::server::assets::{self, Assets};
+    use chat_stream::server::config::ServerConfig;
+    use chat_stream::server::cors::cors;
+    use chat_stream::server::health;
+    use chat_stream::server::metrics::{self, Metrics};
+    use chat_stream::server::preview::{PreviewConfig, PreviewFetcher};
+    use chat_stream::server::shutdown::{stop_signal, Shutdown, StopSignal};
+    use chat_stream::server::telemetry::{self, init_tracing, ClientErrorLimiter, RequestSpan};
+    use chat_stream::server::uploads::{self, UploadConfig, UploadLimiter};
     use leptos::config::get_configuration;
     use leptos::prelude::*;
     use leptos_actix::{generate_route_list, LeptosRoutes};
     use leptos_meta::MetaTags;
+    use std::time::{Duration, Instant};
+    use tracing_actix_web::TracingLogger;
 
-    let conf = get_configuration(None).unwrap();
+    let conf = get_configuration(None)
+        .unwrap_or_else(|e| exit_with_error(&format!("Invalid Leptos configuration: {e}")));
     let addr = conf.leptos_options.site_addr;
+    let config = ServerConfig::load()
+        .unwrap_or_else(|e| exit_with_error(&format!("Invalid server configuration: {e}")));
+    init_tracing(&config)
+        .unwrap_or_else(|e| exit_with_error(&format!("Could not set up logging: {e}")));
 
-    HttpServer::new(move || {
+    // Shared by all workers so they reuse one HTTP client for link previews
+    let preview_fetcher = web::Data::new(
+        PreviewFetcher::new(PreviewConfig::from_config(&config))
+            .unwrap_or_else(|e| exit_with_error(&format!("Could not set up link previews: {e}"))),
+    );
+    let assets = web::Data::new(Assets::from_options(&conf.leptos_options));
+    let upload_config = web::Data::new(UploadConfig::from_config(&config));
+    let upload_limiter = web::Data::new(UploadLimiter::from_config(&config));
+    let client_error_limiter = web::Data::new(ClientErrorLimiter::default());
+    let metrics = web::Data::new(
+        Metrics::new()
+            .unwrap_or_else(|e| exit_with_error(&format!("Could not set up metrics: {e}"))),
+    );
+    let uploads_enabled = config.features.uploads;
+    let allowed_origins = config.allowed_origins.clone();
+    let drain = Duration::from_secs(config.shutdown.drain_seconds);
+    let shutdown_timeout = config.shutdown.timeout_seconds;
+    let config = web::Data::new(config);
+    let shutdown = Shutdown::new();
+    let shutdown_data = web::Data::new(shutdown.clone());
+
+    tracing::info!("listening on http://{}", &addr);
+
+    let server = HttpServer::new(move || {
         // Generate the list of routes in your Leptos App
         let routes = generate_route_list(App);
         let leptos_options = &conf.leptos_options;
         let site_root = leptos_options.site_root.clone().to_string();
 
-        println!("listening on http://{}", &addr);
-
         App::new()
-            // serve JS/WASM/CSS from `pkg`
-            .service(Files::new("/pkg", format!("{site_root}/pkg")))
+            // serve JS/WASM/CSS from `pkg`, precompressed and cached where possible
+            .service(assets::pkg)
             // serve other assets from the `assets` directory
             .service(Files::new("/assets", &site_root))
             // serve the favicon from /favicon.ico
             .service(favicon)
+            // collect errors reported by browsers
+            .service(telemetry::client_error)
+            // expose Prometheus metrics
+            .service(metrics::metrics_endpoint)
+            // liveness and readiness probes for the orchestrator
+            .service(health::healthz)
+            .service(health::readyz)
+            // accept and serve room attachments
+            .configure(|cfg| {
+                if uploads_enabled {
+                    cfg.service(uploads::upload)
+                        .service(uploads::download)
+                        .service(uploads::thumbnail);
+                }
+            })
             .leptos_routes(routes, {
                 let leptos_options = leptos_options.clone();
                 move || {
@@ -48,11 +100,63 @@ async fn main() -> std::io::Result<()> {
                 }
             })
             .app_data(web::Data::new(leptos_options.to_owned()))
-        //.wrap(middleware::Compress::default())
+            .app_data(assets.clone())
+            .app_data(preview_fetcher.clone())
+            .app_data(upload_config.clone())
+            .app_data(upload_limiter.clone())
+            .app_data(client_error_limiter.clone())
+            .app_data(metrics.clone())
+            .app_data(config.clone())
+            .app_data(shutdown_data.clone())
+            .wrap(middleware::from_fn(metrics::track_requests))
+            .wrap(middleware::from_fn(telemetry::request_id_header))
+            .wrap(cors(&allowed_origins))
+            .wrap(TracingLogger::<RequestSpan>::new())
+            .wrap(middleware::Compress::default())
     })
+    // Signals are handled below, so readiness can flip to draining before the listener closes
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout)
     .bind(&addr)?
-    .run()
-    .await
+    .run();
+
+    let handle = server.handle();
+    let (stop_deadline, mut stopping) = tokio::sync::oneshot::channel();
+    actix_web::rt::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            let signal = stop_signal().await;
+            tracing::info!(?signal, "shutting down");
+            shutdown.begin();
+            // A developer's Ctrl-C has no load balancer to wait for
+            if signal == StopSignal::Terminate {
+                actix_web::rt::time::sleep(drain).await;
+            }
+            let _ = stop_deadline.send(Instant::now() + Duration::from_secs(shutdown_timeout));
+            handle.stop(true).await;
+        }
+    });
+
+    server.await?;
+    // Requests and writes share one timeout, so the whole stop fits the orchestrator's grace period
+    let deadline = stopping
+        .try_recv()
+        .unwrap_or_else(|_| Instant::now() + Duration::from_secs(shutdown_timeout));
+    if !shutdown
+        .wait_for_writes(deadline.saturating_duration_since(Instant::now()))
+        .await
+    {
+        tracing::warn!("storage writes still running at shutdown");
+    }
+    tracing::info!("stopped");
+    Ok(())
+}
+
+/// Startup errors are reported as a readable message rather than a panic backtrace.
+#[cfg(feature = "ssr")]
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("error: {message}");
+    std::process::exit(1);
 }
 
 #[cfg(feature = "ssr")]
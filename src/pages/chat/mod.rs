@@ -1,8 +1,20 @@
-use crate::db::{get_user, save_user, User};
+mod upload;
+mod voice;
+
+use crate::crypto::{
+    encrypted_room_code, fingerprint, generate_room_secret, invite_fragment, is_encrypted_room,
+    secret_from_fragment,
+};
+use crate::db::{
+    device_identity, forget_room, get_rooms, get_user, save_user, touch_room, JoinedAs, User,
+};
 use leptos::prelude::*;
 use leptos::task::spawn_local;
 use leptos_router::hooks::{use_navigate, use_query_map};
+use upload::{start_upload, PendingUpload, UploadList};
 use uuid::Uuid;
+use voice::VoiceRecorderButton;
+use web_sys::{ClipboardEvent, DragEvent, FileList};
 
 /// Renders the chat page.
 #[component]
@@ -14,6 +26,10 @@ pub fn ChatPage() -> impl IntoView {
     let (name, set_name) = signal("".to_string());
     let (email, set_email) = signal("".to_string());
     let (phone, set_phone) = signal("".to_string());
+    let (joined_as, set_joined_as) = signal(JoinedAs::Invitee);
+    let (encrypted, set_encrypted) = signal(false);
+    let (room_secret, set_room_secret) = signal(None::<String>);
+    let (invite_secret, set_invite_secret) = signal(None::<String>);
 
     // Derived signal for the room code
     let room_code = Memo::new(move |_| {
@@ -31,12 +47,32 @@ pub fn ChatPage() -> impl IntoView {
         }
     });
 
+    // The key is taken out of the address bar straight away, so it doesn't linger in history,
+    // screenshots or error reports; once joined it lives in IndexedDB
+    Effect::new(move |_| {
+        let location = window().location();
+        let hash = location.hash().unwrap_or_default();
+        if hash.is_empty() {
+            return;
+        }
+        set_invite_secret.set(secret_from_fragment(&hash));
+        let url = format!(
+            "{}{}",
+            location.pathname().unwrap_or_default(),
+            location.search().unwrap_or_default()
+        );
+        if let Ok(history) = window().history() {
+            let _ = history.replace_state_with_url(&wasm_bindgen::JsValue::NULL, "", Some(&url));
+        }
+    });
+
     let on_submit = move |_| {
         let user = User {
             name: name.get(),
             email: email.get(),
             phone: phone.get(),
             created_at: js_sys::Date::now() as u64,
+            device_key: None,
         };
 
         let navigate = navigate.clone();
@@ -46,16 +82,138 @@ pub fn ChatPage() -> impl IntoView {
                 Ok(_) => leptos::logging::log!("User saved successfully"),
                 Err(e) => leptos::logging::error!("Failed to save user: {:?}", e),
             }
+            // Every browser gets its own identity key pair for encrypted rooms
+            if let Err(e) = device_identity().await {
+                leptos::logging::error!("Failed to load device identity: {:?}", e);
+            }
 
-            let current_code = room_code.get();
+            let mut current_code = room_code.get_untracked();
+            let mut joined = JoinedAs::Invitee;
+            let mut secret = invite_secret.get_untracked();
+            if !current_code.is_empty() && secret.is_none() {
+                // Rejoining from the room list or by code: the key is only kept in this browser
+                secret = get_rooms()
+                    .await
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find(|room| room.code == current_code)
+                    .and_then(|room| room.secret);
+            }
+            if is_encrypted_room(&current_code) && secret.is_none() {
+                let _ = window().alert_with_message(
+                    "This room is end-to-end encrypted. Open it with the full invite link, which carries its key.",
+                );
+                return;
+            }
             if current_code.is_empty() {
-                let new_code = Uuid::new_v4().to_string();
-                navigate(&format!("/chat?code={}", new_code), Default::default());
+                secret = None;
+                // An encrypted room must never fall back to plaintext because its key failed
+                if encrypted.get_untracked() {
+                    match generate_room_secret() {
+                        Ok(generated) => secret = Some(generated),
+                        Err(e) => {
+                            leptos::logging::error!("Failed to create room key: {:?}", e);
+                            let _ = window().alert_with_message(&format!(
+                                "Couldn't create a key for the encrypted room, so it wasn't created: {e}"
+                            ));
+                            return;
+                        }
+                    }
+                }
+                let id = Uuid::new_v4().to_string();
+                current_code = if secret.is_some() {
+                    encrypted_room_code(&id)
+                } else {
+                    id
+                };
+                joined = JoinedAs::Creator;
+                // The invite link with the key is built by "Copy invite", never put in the address bar
+                navigate(&format!("/chat?code={}", current_code), Default::default());
+            }
+
+            match touch_room(current_code, joined, secret.clone()).await {
+                Ok(room) => {
+                    set_joined_as.set(room.joined_as);
+                    set_room_secret.set(room.secret);
+                }
+                // Still joined with the key in hand, so an encrypted room never shows in the clear
+                Err(e) => {
+                    leptos::logging::error!("Failed to record room: {:?}", e);
+                    set_joined_as.set(joined);
+                    set_room_secret.set(secret);
+                }
             }
             set_is_joined.set(true);
         });
     };
 
+    // Members compare this out of band to check nobody handed them a different key
+    let room_fingerprint = LocalResource::new(move || {
+        let code = room_code.get();
+        let secret = room_secret.get();
+        async move { fingerprint(&code, &secret?).await.ok() }
+    });
+
+    // Encrypted rooms share the full invite link, since the key travels in its fragment
+    let copy_invite = move |_| {
+        let code = room_code.get_untracked();
+        let invite = match room_secret.get_untracked() {
+            Some(secret) => format!(
+                "{}/chat?code={}{}",
+                window().location().origin().unwrap_or_default(),
+                code,
+                invite_fragment(&secret)
+            ),
+            None => code,
+        };
+        let _ = window().navigator().clipboard().write_text(&invite);
+    };
+
+    // Files dropped or pasted into the composer upload straight away
+    let uploads = RwSignal::new(Vec::<PendingUpload>::new());
+    let upload_files = move |files: FileList| {
+        // Uploads are stored in the clear, so they would leak an encrypted room's contents
+        if room_secret.get_untracked().is_some() {
+            let _ = window().alert_with_message(
+                "Attachments aren't end-to-end encrypted yet, so they're turned off in encrypted rooms.",
+            );
+            return;
+        }
+        let room = room_code.get_untracked();
+        for file in (0..files.length()).filter_map(|i| files.get(i)) {
+            match start_upload(&room, &file, &file.name(), &[]) {
+                Ok(upload) => uploads.update(|list| list.push(upload)),
+                Err(e) => leptos::logging::error!("Failed to start upload: {:?}", e),
+            }
+        }
+    };
+
+    // Leaving only drops the room from this browser; the code still works to rejoin, but the key
+    // of an encrypted room is only kept here and goes with it
+    let leave_navigate = use_navigate();
+    let leave_room = move |_| {
+        let message = if room_secret.get_untracked().is_some() {
+            "Leave this room? Its key is only stored in this browser and will be deleted, so you \
+             will need the full invite link to rejoin."
+        } else {
+            "Leave this room? You can rejoin later with its code."
+        };
+        let confirmed = window().confirm_with_message(message).unwrap_or(false);
+        if !confirmed {
+            return;
+        }
+
+        let navigate = leave_navigate.clone();
+        let code = room_code.get_untracked();
+        spawn_local(async move {
+            if let Err(e) = forget_room(code).await {
+                leptos::logging::error!("Failed to forget room: {:?}", e);
+            }
+            set_is_joined.set(false);
+            navigate("/", Default::default());
+        });
+    };
+
     view! {
         <div class="flex flex-col items-center justify-center min-h-screen w-full bg-gray-50 p-4 md:p-6 transition-all duration-300">
             {move || if !is_joined.get() {
@@ -99,6 +257,19 @@ pub fn ChatPage() -> impl IntoView {
                                 />
                             </div>
 
+                            <Show when=move || room_code.get().is_empty()>
+                                <label class="flex items-center gap-3 ml-1 text-sm font-semibold text-gray-700 cursor-pointer">
+                                    <input
+                                        type="checkbox"
+                                        prop:checked=encrypted
+                                        on:change=move |ev| set_encrypted.set(event_target_checked(&ev))
+                                        class="w-4 h-4 accent-blue-600"
+                                    />
+                                    "End-to-end encrypted"
+                                    <span class="font-normal text-gray-400">"(the key travels only in the invite link)"</span>
+                                </label>
+                            </Show>
+
                             <button
                                 on:click=on_submit.clone()
                                 class="w-full mt-8 py-4 bg-gradient-to-r from-blue-600 to-purple-600 text-white font-bold text-lg rounded-xl shadow-lg hover:shadow-xl hover:-translate-y-0.5 transition-all active:scale-95 duration-200"
@@ -122,7 +293,22 @@ pub fn ChatPage() -> impl IntoView {
                                     <p class="text-xs text-green-500 font-bold flex items-center gap-1.5 uppercase tracking-wide">
                                         <span class="w-2 h-2 rounded-full bg-green-500 animate-pulse"></span>
                                         "Online"
+                                        {move || (joined_as.get() == JoinedAs::Creator).then(|| view! {
+                                            <span class="ml-1 px-2 py-0.5 rounded-full bg-purple-100 text-purple-700 normal-case tracking-normal">
+                                                "Created by you"
+                                            </span>
+                                        })}
                                     </p>
+                                    {move || room_fingerprint.get().flatten().map(|fingerprint| view! {
+                                        <p
+                                            class="mt-1 text-xs text-gray-500 font-medium flex items-center gap-1.5"
+                                            title="Compare this with the other members to verify the room key"
+                                        >
+                                            <svg class="w-3.5 h-3.5 text-green-600" fill="none" stroke="currentColor" viewBox="0 0 24 24"><path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 15v2m-6 4h12a2 2 0 002-2v-6a2 2 0 00-2-2H6a2 2 0 00-2 2v6a2 2 0 002 2zm10-10V7a4 4 0 00-8 0v4h8z"></path></svg>
+                                            "Encrypted"
+                                            <code class="font-mono tracking-wide text-gray-700">{fingerprint}</code>
+                                        </p>
+                                    })}
                                 </div>
                             </div>
 
@@ -131,15 +317,21 @@ pub fn ChatPage() -> impl IntoView {
                                 <code class="font-mono font-bold text-gray-800 text-base md:text-lg">{move || room_code.get()}</code>
                                 <button
                                     class="p-2 hover:bg-white rounded-lg transition-all text-gray-400 hover:text-blue-600 hover:shadow-sm active:scale-95"
-                                    title="Copy Code"
-                                    on:click=move |_| {
-                                        // TODO: Implement copy to clipboard
-                                        leptos::logging::log!("Copy code: {}", room_code.get());
-                                    }
+                                    title=move || if room_secret.get().is_some() { "Copy Invite Link" } else { "Copy Code" }
+                                    on:click=copy_invite
                                 >
                                     <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24"><path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M8 16H6a2 2 0 01-2-2V6a2 2 0 012-2h8a2 2 0 012 2v2m-6 12h8a2 2 0 012 2v6a2 2 0 01-2 2h-8a2 2 0 01-2-2v-6a2 2 0 012-2z"></path></svg>
                                 </button>
                             </div>
+
+                            <div class="flex items-center gap-2">
+                                <button
+                                    class="px-4 py-2.5 text-sm font-semibold text-gray-600 bg-gray-50 border border-gray-200 rounded-xl hover:bg-gray-100 transition-all active:scale-95"
+                                    on:click=leave_room.clone()
+                                >
+                                    "Leave"
+                                </button>
+                            </div>
                         </div>
 
                         // Chat Area (Placeholder)
@@ -152,13 +344,33 @@ pub fn ChatPage() -> impl IntoView {
                         </div>
 
                         // Input Area (Placeholder)
-                        <div class="p-4 bg-white rounded-2xl shadow-sm border border-white/20">
+                        <div
+                            class="p-4 bg-white rounded-2xl shadow-sm border border-white/20"
+                            on:dragover=move |ev: DragEvent| ev.prevent_default()
+                            on:drop=move |ev: DragEvent| {
+                                ev.prevent_default();
+                                if let Some(files) = ev.data_transfer().and_then(|dt| dt.files()) {
+                                    upload_files(files);
+                                }
+                            }
+                        >
+                             <UploadList uploads=uploads/>
                              <div class="flex gap-3">
                                 <input
                                     type="text"
                                     placeholder="Type a message..."
+                                    on:paste=move |ev: ClipboardEvent| {
+                                        let files = ev.clipboard_data().and_then(|dt| dt.files());
+                                        if let Some(files) = files.filter(|files| files.length() > 0) {
+                                            ev.prevent_default();
+                                            upload_files(files);
+                                        }
+                                    }
                                     class="flex-1 px-6 py-4 bg-gray-50 border border-gray-200 rounded-2xl focus:outline-none focus:bg-white focus:ring-2 focus:ring-blue-500 focus:border-transparent transition-all shadow-sm font-medium"
                                 />
+                                <Show when=move || room_secret.get().is_none()>
+                                    <VoiceRecorderButton room_code=room_code uploads=uploads/>
+                                </Show>
                                 <button class="px-8 py-4 bg-gradient-to-r from-blue-600 to-purple-600 text-white font-bold rounded-2xl hover:shadow-lg hover:-translate-y-0.5 transition-all active:scale-95 flex items-center gap-2 group">
                                     <span>"Send"</span>
                                     <svg class="w-5 h-5 transform rotate-90 group-hover:translate-x-1 transition-transform" fill="none" stroke="currentColor" viewBox="0 0 24 24"><path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 19l9 2-9-18-9 18 9-2zm0 0v-8"></path></svg>
@@ -0,0 +1,119 @@
+use crate::attachment::{Attachment, AttachmentPreview};
+use crate::telemetry::{report_error, session_id, CLIENT_SESSION_HEADER, REQUEST_ID_HEADER};
+use leptos::prelude::*;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Blob, FormData, ProgressEvent, XmlHttpRequest};
+
+/// A file being uploaded from the composer, tracked until the server responds.
+#[derive(Clone)]
+pub struct PendingUpload {
+    pub name: String,
+    pub progress: RwSignal<f64>,
+    pub result: RwSignal<Option<Result<Attachment, String>>>,
+}
+
+/// Uploads `blob` to the room as `name`, sending `fields` ahead of it.
+/// XHR is used instead of fetch because only it reports upload progress.
+pub fn start_upload(
+    room: &str,
+    blob: &Blob,
+    name: &str,
+    fields: &[(&str, String)],
+) -> Result<PendingUpload, String> {
+    let upload = PendingUpload {
+        name: name.to_string(),
+        progress: RwSignal::new(0.0),
+        result: RwSignal::new(None),
+    };
+
+    let form = FormData::new().map_err(js_error)?;
+    for (key, value) in fields {
+        form.append_with_str(key, value).map_err(js_error)?;
+    }
+    form.append_with_blob_and_filename("file", blob, name)
+        .map_err(js_error)?;
+
+    let xhr = XmlHttpRequest::new().map_err(js_error)?;
+    xhr.open("POST", &format!("/uploads/{}", room))
+        .map_err(js_error)?;
+    xhr.set_request_header(CLIENT_SESSION_HEADER, &session_id())
+        .map_err(js_error)?;
+
+    let progress = upload.progress;
+    let on_progress = Closure::<dyn FnMut(ProgressEvent)>::new(move |ev: ProgressEvent| {
+        if ev.length_computable() && ev.total() > 0.0 {
+            progress.set(ev.loaded() / ev.total());
+        }
+    });
+    xhr.upload()
+        .map_err(js_error)?
+        .set_onprogress(Some(on_progress.as_ref().unchecked_ref()));
+    on_progress.forget();
+
+    let result = upload.result;
+    let on_done = Closure::<dyn FnMut()>::new({
+        let xhr = xhr.clone();
+        move || {
+            let body = xhr.response_text().ok().flatten().unwrap_or_default();
+            let outcome = match xhr.status() {
+                Ok(201) => leptos::serde_json::from_str::<Attachment>(&body)
+                    .map_err(|e| format!("Deserialization error: {}", e)),
+                _ if body.is_empty() => Err("Upload failed".to_string()),
+                _ => Err(body),
+            };
+            if let Err(e) = &outcome {
+                let request_id = xhr.get_response_header(REQUEST_ID_HEADER).ok().flatten();
+                report_error(&format!("Upload failed: {}", e), request_id);
+            }
+            progress.set(1.0);
+            result.set(Some(outcome));
+        }
+    });
+    xhr.set_onloadend(Some(on_done.as_ref().unchecked_ref()));
+    on_done.forget();
+
+    xhr.send_with_opt_form_data(Some(&form)).map_err(js_error)?;
+
+    Ok(upload)
+}
+
+pub(super) fn js_error(e: JsValue) -> String {
+    format!("{:?}", e)
+}
+
+/// Progress bars for the uploads started from the composer.
+#[component]
+pub fn UploadList(uploads: RwSignal<Vec<PendingUpload>>) -> impl IntoView {
+    view! {
+        <div class="flex flex-col gap-2 empty:hidden mb-3">
+            {move || uploads.get().into_iter().map(|upload| view! { <UploadItem upload=upload/> }).collect_view()}
+        </div>
+    }
+}
+
+#[component]
+fn UploadItem(upload: PendingUpload) -> impl IntoView {
+    let progress = upload.progress;
+    let result = upload.result;
+
+    view! {
+        <div class="flex items-center gap-3 px-4 py-2 bg-gray-50 rounded-xl border border-gray-200 text-sm">
+            <span class="flex-1 truncate font-medium text-gray-700">{upload.name}</span>
+            {move || match result.get() {
+                None => view! {
+                    <div class="w-32 h-2 bg-gray-200 rounded-full overflow-hidden">
+                        <div
+                            class="h-full bg-gradient-to-r from-blue-600 to-purple-600 transition-all"
+                            style:width=move || format!("{:.0}%", progress.get() * 100.0)
+                        ></div>
+                    </div>
+                }.into_any(),
+                Some(Ok(attachment)) => view! { <AttachmentPreview attachment=attachment/> }.into_any(),
+                Some(Err(e)) => view! {
+                    <span class="font-semibold text-red-600" title=e>"Failed"</span>
+                }.into_any(),
+            }}
+        </div>
+    }
+}
@@ -0,0 +1,258 @@
+use super::upload::{js_error, start_upload, PendingUpload};
+use js_sys::Array;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AudioBuffer, AudioContext, Blob, BlobEvent, BlobPropertyBag, MediaRecorder, MediaStream,
+    MediaStreamConstraints, MediaStreamTrack,
+};
+
+const WAVEFORM_BARS: usize = 40;
+
+/// A microphone recording in progress.
+pub struct Recording {
+    recorder: MediaRecorder,
+    cancelled: Rc<Cell<bool>>,
+}
+
+impl Recording {
+    /// Stops recording; the `on_finish` callback given to `start_recording` then receives the audio.
+    pub fn stop(&self) {
+        let _ = self.recorder.stop();
+    }
+
+    /// Stops recording and releases the microphone without handing the audio to `on_finish`.
+    pub fn cancel(&self) {
+        self.cancelled.set(true);
+        self.stop();
+    }
+}
+
+/// Asks for the microphone and starts recording. Once stopped, `on_finish` gets the audio and
+/// its length in milliseconds, and the microphone is released.
+pub async fn start_recording(
+    on_finish: impl FnOnce(Blob, u64) + 'static,
+) -> Result<Recording, String> {
+    let constraints = MediaStreamConstraints::new();
+    constraints.set_audio(&JsValue::TRUE);
+    let request = window()
+        .navigator()
+        .media_devices()
+        .map_err(js_error)?
+        .get_user_media_with_constraints(&constraints)
+        .map_err(js_error)?;
+    let stream: MediaStream = JsFuture::from(request)
+        .await
+        .map_err(js_error)?
+        .unchecked_into();
+    let recorder = MediaRecorder::new_with_media_stream(&stream).map_err(js_error)?;
+
+    let chunks = Array::new();
+    let on_data = Closure::<dyn FnMut(BlobEvent)>::new({
+        let chunks = chunks.clone();
+        move |ev: BlobEvent| {
+            if let Some(data) = ev.data() {
+                chunks.push(&data);
+            }
+        }
+    });
+    recorder.set_ondataavailable(Some(on_data.as_ref().unchecked_ref()));
+    on_data.forget();
+
+    // MediaRecorder output often has no duration in its header, so it is measured here
+    let started = js_sys::Date::now();
+    let cancelled = Rc::new(Cell::new(false));
+    let on_stop = Closure::once({
+        let recorder = recorder.clone();
+        let cancelled = cancelled.clone();
+        move || {
+            for track in stream.get_tracks().iter() {
+                track.unchecked_into::<MediaStreamTrack>().stop();
+            }
+            if cancelled.get() {
+                return;
+            }
+            let options = BlobPropertyBag::new();
+            options.set_type(&recorder.mime_type());
+            match Blob::new_with_blob_sequence_and_options(&chunks, &options) {
+                Ok(blob) => on_finish(blob, (js_sys::Date::now() - started) as u64),
+                Err(e) => leptos::logging::error!("Failed to assemble recording: {:?}", e),
+            }
+        }
+    });
+    recorder.set_onstop(Some(on_stop.as_ref().unchecked_ref()));
+    on_stop.forget();
+
+    recorder.start().map_err(js_error)?;
+    Ok(Recording {
+        recorder,
+        cancelled,
+    })
+}
+
+/// Decodes a recording and reduces it to peak amplitudes scaled to 0-255, one per waveform bar.
+pub async fn compute_waveform(blob: &Blob) -> Result<Vec<u8>, String> {
+    let buffer = JsFuture::from(blob.array_buffer())
+        .await
+        .map_err(js_error)?;
+    let context = AudioContext::new().map_err(js_error)?;
+    let decoding = context
+        .decode_audio_data(buffer.unchecked_ref())
+        .map_err(js_error)?;
+    let audio: AudioBuffer = JsFuture::from(decoding)
+        .await
+        .map_err(js_error)?
+        .unchecked_into();
+    let _ = context.close();
+
+    let samples = audio.get_channel_data(0).map_err(js_error)?;
+    Ok(peaks(&samples, WAVEFORM_BARS))
+}
+
+fn peaks(samples: &[f32], bars: usize) -> Vec<u8> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    let peaks: Vec<f32> = samples
+        .chunks(samples.len().div_ceil(bars))
+        .map(|chunk| chunk.iter().fold(0.0, |max: f32, s| max.max(s.abs())))
+        .collect();
+    let loudest = peaks.iter().copied().fold(0.0, f32::max);
+    if loudest == 0.0 {
+        return vec![0; peaks.len()];
+    }
+    peaks
+        .iter()
+        .map(|peak| (peak / loudest * 255.0).round() as u8)
+        .collect()
+}
+
+fn file_name(mime: &str) -> &'static str {
+    if mime.contains("mp4") {
+        "voice-note.m4a"
+    } else if mime.contains("ogg") {
+        "voice-note.ogg"
+    } else {
+        "voice-note.webm"
+    }
+}
+
+/// Composer button that records a voice note on the first click and uploads it on the second.
+#[component]
+pub fn VoiceRecorderButton(
+    room_code: Memo<String>,
+    uploads: RwSignal<Vec<PendingUpload>>,
+) -> impl IntoView {
+    let recording = StoredValue::new_local(None::<Recording>);
+    let (is_recording, set_is_recording) = signal(false);
+    let (is_starting, set_is_starting) = signal(false);
+
+    let on_finish = move |blob: Blob, duration_ms: u64| {
+        // Read now: the room's signals may be gone by the time the waveform is computed
+        let Some(room) = room_code.try_get_untracked() else {
+            return;
+        };
+        spawn_local(async move {
+            // A recording the browser can't decode still uploads, just without bars
+            let waveform = compute_waveform(&blob)
+                .await
+                .unwrap_or_default()
+                .iter()
+                .map(u8::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+            let fields = [
+                ("duration_ms", duration_ms.to_string()),
+                ("waveform", waveform),
+            ];
+            let name = file_name(&blob.type_());
+            match start_upload(&room, &blob, name, &fields) {
+                Ok(upload) => {
+                    uploads.try_update(|list| list.push(upload));
+                }
+                Err(e) => leptos::logging::error!("Failed to upload voice note: {:?}", e),
+            }
+        });
+    };
+
+    let toggle = move |_| {
+        if is_recording.get_untracked() {
+            recording.with_value(|recording| {
+                if let Some(recording) = recording {
+                    recording.stop();
+                }
+            });
+            recording.set_value(None);
+            set_is_recording.set(false);
+            return;
+        }
+
+        set_is_starting.set(true);
+        spawn_local(async move {
+            match start_recording(on_finish).await {
+                Ok(started) => {
+                    // The button may have gone away while the browser asked for the microphone
+                    if let Some(Some(started)) = recording.try_set_value(Some(started)) {
+                        started.cancel();
+                        return;
+                    }
+                    set_is_recording.set(true);
+                }
+                Err(e) => leptos::logging::error!("Microphone unavailable: {:?}", e),
+            }
+            set_is_starting.set(false);
+        });
+    };
+
+    // Leaving the room mid-recording must not keep the microphone live or send a half-finished note
+    on_cleanup(move || {
+        if let Some(Some(recording)) = recording.try_update_value(Option::take) {
+            recording.cancel();
+        }
+    });
+
+    view! {
+        <button
+            type="button"
+            on:click=toggle
+            disabled=move || is_starting.get()
+            title=move || if is_recording.get() { "Stop and send" } else { "Record a voice note" }
+            class=move || if is_recording.get() {
+                "px-5 py-4 bg-red-500 text-white rounded-2xl shadow-sm animate-pulse transition-all active:scale-95"
+            } else {
+                "px-5 py-4 bg-gray-50 text-gray-500 border border-gray-200 rounded-2xl hover:text-blue-600 hover:bg-white transition-all active:scale-95 disabled:opacity-50"
+            }
+        >
+            <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24"><path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M19 11a7 7 0 01-7 7m0 0a7 7 0 01-7-7m7 7v4m0 0H8m4 0h4m-4-8a3 3 0 01-3-3V5a3 3 0 116 0v6a3 3 0 01-3 3z"></path></svg>
+        </button>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peaks_are_scaled_to_the_loudest_bar() {
+        let samples = [0.1, -0.5, 0.25, 0.0, -0.1, 0.05];
+        assert_eq!(peaks(&samples, 3), [255, 128, 51]);
+    }
+
+    #[test]
+    fn peaks_never_exceed_the_bar_count() {
+        let samples: Vec<f32> = (0..1000).map(|i| (i as f32 / 10.0).sin()).collect();
+        assert_eq!(peaks(&samples, WAVEFORM_BARS).len(), WAVEFORM_BARS);
+        assert_eq!(peaks(&samples[..7], WAVEFORM_BARS).len(), 7);
+    }
+
+    #[test]
+    fn silence_and_empty_recordings() {
+        assert_eq!(peaks(&[0.0; 10], 5), [0; 5]);
+        assert!(peaks(&[], 5).is_empty());
+    }
+}
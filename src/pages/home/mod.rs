@@ -1,3 +1,4 @@
+use crate::db::{get_rooms, RoomEntry};
 use leptos::prelude::*;
 
 /// Renders the home page of your application.
@@ -5,6 +6,9 @@ use leptos::prelude::*;
 pub fn HomePage() -> impl IntoView {
     let (invitation_code, set_invitation_code) = signal("".to_string());
 
+    // Joined rooms live in IndexedDB, so this only resolves in the browser
+    let rooms = LocalResource::new(move || async move { get_rooms().await.unwrap_or_default() });
+
     let on_submit_code = move |_| {
         let code = invitation_code.get();
         if !code.is_empty() {
@@ -16,7 +20,7 @@ pub fn HomePage() -> impl IntoView {
     };
 
     view! {
-        <div class="flex flex-col items-center justify-center h-screen w-full bg-gray-50 text-gray-800">
+        <div class="flex flex-col items-center justify-center min-h-screen w-full py-12 bg-gray-50 text-gray-800">
             // Logo Section
             <div class="mb-8 rotate-3 transition-transform hover:rotate-0 duration-300">
                 <h1 class="text-6xl font-extrabold text-transparent bg-clip-text bg-gradient-to-r from-blue-600 to-purple-600 drop-shadow-sm">
@@ -67,6 +71,54 @@ pub fn HomePage() -> impl IntoView {
                     ></path>
                 </svg>
             </a>
+
+            // Recent Rooms Section
+            {move || {
+                let rooms = rooms.get().unwrap_or_default();
+                (!rooms.is_empty()).then(|| view! {
+                    <div class="mt-12 w-full max-w-md px-4">
+                        <h2 class="text-sm font-bold text-gray-500 uppercase tracking-wider mb-3 text-left">"Your rooms"</h2>
+                        <ul class="flex flex-col gap-2">
+                            {rooms.into_iter().map(|room| view! { <RoomListItem room=room/> }).collect_view()}
+                        </ul>
+                    </div>
+                })
+            }}
         </div>
     }
 }
+
+/// A single entry in the "Your rooms" list.
+#[component]
+fn RoomListItem(room: RoomEntry) -> impl IntoView {
+    let href = format!("/chat?code={}", room.code);
+    let last_activity = format_last_activity(room.last_activity);
+
+    view! {
+        <li>
+            <a
+                href=href
+                class="flex items-center gap-4 p-4 bg-white rounded-xl border border-gray-200 shadow-sm hover:border-blue-200 hover:bg-blue-50/50 transition-all text-left"
+            >
+                <div class="flex-1 min-w-0">
+                    <div class="flex items-center justify-between gap-2">
+                        <span class="font-semibold text-gray-800 truncate">{room.name}</span>
+                        <span class="text-xs text-gray-400 whitespace-nowrap">{last_activity}</span>
+                    </div>
+                </div>
+            </a>
+        </li>
+    }
+}
+
+/// Formats a millisecond timestamp relative to now, e.g. "5m ago".
+fn format_last_activity(timestamp: u64) -> String {
+    let now = js_sys::Date::now() as u64;
+    let minutes = now.saturating_sub(timestamp) / 60_000;
+    match minutes {
+        0 => "just now".to_string(),
+        1..=59 => format!("{}m ago", minutes),
+        60..=1439 => format!("{}h ago", minutes / 60),
+        _ => format!("{}d ago", minutes / 1440),
+    }
+}
@@ -0,0 +1,35 @@
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// OpenGraph metadata for a URL found in a message. There is no `og:image`: loading it from the
+/// reader's browser would tell the linked site who read the message, and when.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+}
+
+/// A compact card showing a link preview.
+#[component]
+pub fn LinkPreviewCard(preview: LinkPreview) -> impl IntoView {
+    view! {
+        <a
+            href=preview.url
+            target="_blank"
+            rel="noopener noreferrer nofollow"
+            class="block mt-2 max-w-md p-3 bg-white rounded-xl border border-gray-200 shadow-sm hover:border-blue-200 transition-all text-left"
+        >
+            <div class="min-w-0">
+                {preview.site_name.map(|site_name| view! {
+                    <p class="text-xs font-semibold text-gray-400 uppercase tracking-wide truncate">{site_name}</p>
+                })}
+                <p class="font-semibold text-gray-800 truncate">{preview.title}</p>
+                {preview.description.map(|description| view! {
+                    <p class="text-sm text-gray-500 line-clamp-2">{description}</p>
+                })}
+            </div>
+        </a>
+    }
+}
@@ -0,0 +1,157 @@
+use actix_files::NamedFile;
+use actix_web::error::ErrorNotFound;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use leptos::config::LeptosOptions;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+const REVALIDATE: &str = "no-cache";
+
+/// Precompressed siblings looked up next to each file, in order of preference.
+const PRECOMPRESSED: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+/// Bundles cargo-leptos hashes, by the key `hash.txt` lists them under, which is also their extension.
+const HASHED_KINDS: [&str; 3] = ["js", "wasm", "css"];
+
+/// Where the cargo-leptos build output (JS, WASM and CSS) is served from.
+pub struct Assets {
+    pub pkg_dir: PathBuf,
+    /// File names known to be content-addressed, from `hash.txt`.
+    immutable: HashSet<String>,
+}
+
+impl Assets {
+    /// Serves `pkg_dir` with every file revalidated.
+    pub fn new(pkg_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            pkg_dir: pkg_dir.into(),
+            immutable: HashSet::new(),
+        }
+    }
+
+    /// Serves the site's pkg dir, caching the bundles of this build for a year when file hashing
+    /// is on. Like the hydration scripts, it reads the hashes from the hash file next to the
+    /// server binary; without it nothing is cached as immutable.
+    pub fn from_options(options: &LeptosOptions) -> Self {
+        let assets =
+            Self::new(Path::new(options.site_root.as_ref()).join(options.site_pkg_dir.as_ref()));
+        if !options.hash_files {
+            return assets;
+        }
+        let hash_file = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join(options.hash_file.as_ref())))
+            .unwrap_or_else(|| PathBuf::from(options.hash_file.as_ref()));
+        match std::fs::read_to_string(&hash_file) {
+            Ok(hashes) => assets.with_hashes(&options.output_name, &hashes),
+            Err(e) => {
+                tracing::warn!("could not read {}: {}", hash_file.display(), e);
+                assets
+            }
+        }
+    }
+
+    /// Marks the bundles listed in a cargo-leptos `hash.txt` (`js: <hash>` lines and so on) as
+    /// immutable. They are named `<output_name>.<hash>.<kind>`.
+    pub fn with_hashes(mut self, output_name: &str, hashes: &str) -> Self {
+        for (kind, hash) in hashes.lines().filter_map(|line| line.split_once(':')) {
+            let (kind, hash) = (kind.trim(), hash.trim());
+            if HASHED_KINDS.contains(&kind) && !hash.is_empty() {
+                self.immutable
+                    .insert(format!("{}.{}.{}", output_name, hash, kind));
+            }
+        }
+        self
+    }
+}
+
+/// Serves the site bundle. A `.br` or `.gz` file built alongside the requested one is sent
+/// instead when the browser accepts it, so the `Compress` middleware doesn't redo the work on
+/// every request. The hashed bundles of the running build never change content and are cached
+/// for a year; anything else is revalidated against its ETag.
+#[get("/pkg/{file:.*}")]
+pub async fn pkg(
+    assets: web::Data<Assets>,
+    file: web::Path<String>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let relative = safe_path(&file).ok_or_else(|| ErrorNotFound("not found"))?;
+    let path = assets.pkg_dir.join(&relative);
+    if !path.is_file() {
+        return Err(ErrorNotFound("not found"));
+    }
+
+    // Typed by the requested name, since `.br` and `.gz` say nothing about the content
+    let content_type = actix_files::file_extension_to_mime(
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default(),
+    );
+    let (served, encoding) = PRECOMPRESSED
+        .iter()
+        .filter(|(coding, _)| accepts(&req, coding))
+        .map(|(coding, ext)| (with_suffix(&path, ext), Some(*coding)))
+        .find(|(candidate, _)| candidate.is_file())
+        .unwrap_or((path, None));
+
+    let mut response = NamedFile::open_async(&served)
+        .await?
+        .set_content_type(content_type)
+        .into_response(&req);
+
+    let success = response.status().is_success();
+    let headers = response.headers_mut();
+    if let Some(encoding) = encoding.filter(|_| success) {
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    }
+    headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(if assets.immutable.contains(file.as_str()) {
+            IMMUTABLE
+        } else {
+            REVALIDATE
+        }),
+    );
+    Ok(response)
+}
+
+// Only plain file names below the pkg dir; `..`, absolute paths and backslashes are refused
+fn safe_path(file: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for segment in file.split('/') {
+        if segment.is_empty() || segment.starts_with('.') || segment.contains('\\') {
+            return None;
+        }
+        path.push(segment);
+    }
+    (!path.as_os_str().is_empty()).then_some(path)
+}
+
+fn with_suffix(path: &Path, ext: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(ext);
+    PathBuf::from(name)
+}
+
+/// Whether `Accept-Encoding` lists `coding` without ruling it out with `q=0`.
+fn accepts(req: &HttpRequest, coding: &str) -> bool {
+    req.headers()
+        .get_all(header::ACCEPT_ENCODING)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let refused = params.any(|param| {
+                param
+                    .trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    == Some(0.0)
+            });
+            name.eq_ignore_ascii_case(coding) && !refused
+        })
+}
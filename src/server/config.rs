@@ -0,0 +1,367 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const DEFAULT_CONFIG_FILE: &str = "chat_stream.toml";
+const LOG_LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+
+/// Server settings beyond the Leptos site options. Read from `chat_stream.toml` (or the file
+/// named by `CHAT_STREAM_CONFIG`), then overridden by `CHAT_STREAM_*` environment variables.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub upload_dir: PathBuf,
+    pub max_upload_bytes: usize,
+    pub rate_limits: RateLimits,
+    /// Origins allowed to make cross-origin requests, e.g. `https://chat.example.com`.
+    pub allowed_origins: Vec<String>,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub features: Features,
+    pub shutdown: ShutdownSettings,
+}
+
+/// How log lines are written: readable text for development, JSON for log shippers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Per-client limits, counted per minute by client address.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub uploads_per_minute: u32,
+}
+
+/// Optional features that can be switched off per deployment.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    /// Fetching link previews makes outbound requests; turn it off for offline deployments.
+    pub link_previews: bool,
+    pub uploads: bool,
+}
+
+/// How a redeploy winds the server down, in seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownSettings {
+    /// After SIGTERM, how long `/readyz` reports draining before new connections are refused,
+    /// giving the load balancer time to send traffic elsewhere.
+    pub drain_seconds: u64,
+    /// How long in-flight requests and storage writes, together, get to finish once the server
+    /// stops. The orchestrator's grace period must cover `drain_seconds + timeout_seconds`.
+    pub timeout_seconds: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            upload_dir: PathBuf::from("uploads"),
+            max_upload_bytes: 10 * 1024 * 1024,
+            rate_limits: RateLimits::default(),
+            allowed_origins: Vec::new(),
+            log_level: "info".to_string(),
+            log_format: LogFormat::default(),
+            features: Features::default(),
+            shutdown: ShutdownSettings::default(),
+        }
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            uploads_per_minute: 10,
+        }
+    }
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            link_previews: true,
+            uploads: true,
+        }
+    }
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        Self {
+            // 25s in all, inside the 30s grace period Kubernetes and Docker give by default
+            drain_seconds: 5,
+            timeout_seconds: 20,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Loads and validates the configuration. A missing default file is fine; a missing file
+    /// named by `CHAT_STREAM_CONFIG` is not.
+    pub fn load() -> Result<Self, String> {
+        let mut config = match std::env::var_os("CHAT_STREAM_CONFIG") {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read config file {}: {}", path.display(), e))?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    /// Applies `CHAT_STREAM_*` overrides, looking variables up through `var`.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        if let Some(dir) = var("CHAT_STREAM_UPLOAD_DIR") {
+            self.upload_dir = PathBuf::from(dir);
+        }
+        override_parsed(
+            &var,
+            "CHAT_STREAM_MAX_UPLOAD_BYTES",
+            &mut self.max_upload_bytes,
+        )?;
+        override_parsed(
+            &var,
+            "CHAT_STREAM_UPLOADS_PER_MINUTE",
+            &mut self.rate_limits.uploads_per_minute,
+        )?;
+        if let Some(origins) = var("CHAT_STREAM_ALLOWED_ORIGINS") {
+            self.allowed_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(level) = var("CHAT_STREAM_LOG_LEVEL") {
+            self.log_level = level;
+        }
+        if let Some(format) = var("CHAT_STREAM_LOG_FORMAT") {
+            self.log_format = match format.trim().to_ascii_lowercase().as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                _ => {
+                    return Err(format!(
+                        "CHAT_STREAM_LOG_FORMAT must be text or json, got \"{}\"",
+                        format
+                    ))
+                }
+            };
+        }
+        override_flag(
+            &var,
+            "CHAT_STREAM_LINK_PREVIEWS",
+            &mut self.features.link_previews,
+        )?;
+        override_flag(&var, "CHAT_STREAM_UPLOADS", &mut self.features.uploads)?;
+        override_parsed(
+            &var,
+            "CHAT_STREAM_SHUTDOWN_DRAIN_SECONDS",
+            &mut self.shutdown.drain_seconds,
+        )?;
+        override_parsed(
+            &var,
+            "CHAT_STREAM_SHUTDOWN_TIMEOUT_SECONDS",
+            &mut self.shutdown.timeout_seconds,
+        )?;
+        Ok(())
+    }
+
+    /// Checks the settings make sense together, naming the offending setting on failure.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.upload_dir.as_os_str().is_empty() {
+            return Err("upload_dir must not be empty".to_string());
+        }
+        if self.max_upload_bytes == 0 {
+            return Err("max_upload_bytes must be greater than 0".to_string());
+        }
+        if self.rate_limits.uploads_per_minute == 0 {
+            return Err("rate_limits.uploads_per_minute must be greater than 0".to_string());
+        }
+        if self.shutdown.timeout_seconds == 0 {
+            return Err("shutdown.timeout_seconds must be greater than 0".to_string());
+        }
+        if !LOG_LEVELS.contains(&self.log_level.to_ascii_lowercase().as_str()) {
+            return Err(format!(
+                "log_level must be one of {}, got \"{}\"",
+                LOG_LEVELS.join(", "),
+                self.log_level
+            ));
+        }
+        for origin in &self.allowed_origins {
+            if !is_valid_origin(origin) {
+                return Err(format!(
+                    "allowed_origins entry \"{}\" must look like https://host[:port], without a path",
+                    origin
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn override_parsed<T: FromStr>(
+    var: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    target: &mut T,
+) -> Result<(), String> {
+    if let Some(value) = var(name) {
+        *target = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} must be a whole number, got \"{}\"", name, value))?;
+    }
+    Ok(())
+}
+
+fn override_flag(
+    var: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    target: &mut bool,
+) -> Result<(), String> {
+    if let Some(value) = var(name) {
+        *target = match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "on" | "yes" => true,
+            "0" | "false" | "off" | "no" => false,
+            _ => return Err(format!("{} must be on or off, got \"{}\"", name, value)),
+        };
+    }
+    Ok(())
+}
+
+fn is_valid_origin(origin: &str) -> bool {
+    let Some(host) = origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+    else {
+        return false;
+    };
+    !host.is_empty() && !host.contains(['/', '?', '#', ' '])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn with_env(vars: &[(&str, &str)]) -> Result<ServerConfig, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let mut config = ServerConfig::default();
+        config.apply_env(|name| vars.get(name).cloned())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(ServerConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn env_overrides_the_file() {
+        let mut config: ServerConfig = toml::from_str(
+            r#"
+            upload_dir = "/srv/uploads"
+            max_upload_bytes = 1024
+            log_level = "debug"
+
+            [rate_limits]
+            uploads_per_minute = 5
+
+            [features]
+            uploads = false
+            "#,
+        )
+        .unwrap();
+        let vars = HashMap::from([
+            ("CHAT_STREAM_MAX_UPLOAD_BYTES", " 2048 "),
+            ("CHAT_STREAM_UPLOADS", "on"),
+            ("CHAT_STREAM_LINK_PREVIEWS", "OFF"),
+            (
+                "CHAT_STREAM_ALLOWED_ORIGINS",
+                "https://a.example, ,http://b.example:8080",
+            ),
+        ]);
+        config
+            .apply_env(|name| vars.get(name).map(|v| v.to_string()))
+            .unwrap();
+
+        // Overridden by the environment
+        assert_eq!(config.max_upload_bytes, 2048);
+        assert!(config.features.uploads);
+        assert!(!config.features.link_previews);
+        assert_eq!(
+            config.allowed_origins,
+            ["https://a.example", "http://b.example:8080"]
+        );
+        // Kept from the file
+        assert_eq!(config.upload_dir, PathBuf::from("/srv/uploads"));
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.rate_limits.uploads_per_minute, 5);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_numbers_and_flags() {
+        let err = with_env(&[("CHAT_STREAM_MAX_UPLOAD_BYTES", "10MB")]).unwrap_err();
+        assert!(err.contains("CHAT_STREAM_MAX_UPLOAD_BYTES"), "{err}");
+        let err = with_env(&[("CHAT_STREAM_UPLOADS_PER_MINUTE", "-1")]).unwrap_err();
+        assert!(err.contains("CHAT_STREAM_UPLOADS_PER_MINUTE"), "{err}");
+        let err = with_env(&[("CHAT_STREAM_UPLOADS", "maybe")]).unwrap_err();
+        assert!(err.contains("CHAT_STREAM_UPLOADS"), "{err}");
+        let err = with_env(&[("CHAT_STREAM_LOG_FORMAT", "xml")]).unwrap_err();
+        assert!(err.contains("CHAT_STREAM_LOG_FORMAT"), "{err}");
+
+        let err = with_env(&[("CHAT_STREAM_UPLOADS_PER_MINUTE", "0")]).unwrap_err();
+        assert!(err.contains("rate_limits.uploads_per_minute"), "{err}");
+        let err = with_env(&[("CHAT_STREAM_LOG_LEVEL", "loud")]).unwrap_err();
+        assert!(err.contains("log_level"), "{err}");
+    }
+
+    #[test]
+    fn rejects_invalid_origins() {
+        for origin in [
+            "chat.example.com",
+            "https://",
+            "https://chat.example.com/",
+            "https://chat.example.com/path",
+            "ftp://chat.example.com",
+        ] {
+            let err = with_env(&[("CHAT_STREAM_ALLOWED_ORIGINS", origin)]).unwrap_err();
+            assert!(err.contains("allowed_origins"), "{origin}: {err}");
+        }
+        assert!(with_env(&[(
+            "CHAT_STREAM_ALLOWED_ORIGINS",
+            "https://chat.example.com:8443"
+        )])
+        .is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_toml_keys() {
+        let err = toml::from_str::<ServerConfig>("max_upload_byte = 10").unwrap_err();
+        assert!(err.to_string().contains("max_upload_byte"), "{err}");
+        let err =
+            toml::from_str::<ServerConfig>("[rate_limits]\nmessages_per_minute = 60").unwrap_err();
+        assert!(err.to_string().contains("messages_per_minute"), "{err}");
+        let err = toml::from_str::<ServerConfig>("database_path = \"chat.db\"").unwrap_err();
+        assert!(err.to_string().contains("database_path"), "{err}");
+        assert!(toml::from_str::<ServerConfig>("[shutdown]\ndrain = 5").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::telemetry::{CLIENT_SESSION_HEADER, REQUEST_ID_HEADER};
+use actix_cors::Cors;
+use actix_web::http::{header, Method};
+
+/// CORS for the configured `allowed_origins`. With none configured, no cross-origin request
+/// gets CORS headers, so browsers keep every response same-origin.
+pub fn cors(allowed_origins: &[String]) -> Cors {
+    allowed_origins.iter().fold(
+        Cors::default()
+            .allowed_methods([Method::GET, Method::POST])
+            .allowed_headers([
+                header::CONTENT_TYPE,
+                header::HeaderName::from_static(CLIENT_SESSION_HEADER),
+            ])
+            .expose_headers([header::HeaderName::from_static(REQUEST_ID_HEADER)])
+            .max_age(3600),
+        |cors, origin| cors.allowed_origin(origin),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse};
+
+    macro_rules! app {
+        ($origins:expr) => {
+            test::init_service(
+                App::new()
+                    .route("/", web::get().to(HttpResponse::Ok))
+                    .wrap(cors($origins)),
+            )
+            .await
+        };
+    }
+
+    #[actix_web::test]
+    async fn allows_configured_origins_only() {
+        let app = app!(&["https://chat.example.com".to_string()]);
+
+        let req = test::TestRequest::get()
+            .insert_header((header::ORIGIN, "https://chat.example.com"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(
+            response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(&header::HeaderValue::from_static(
+                "https://chat.example.com"
+            ))
+        );
+
+        let req = test::TestRequest::get()
+            .insert_header((header::ORIGIN, "https://evil.example"))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert!(response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[actix_web::test]
+    async fn same_origin_requests_are_unaffected() {
+        let app = app!(&[]);
+        let req = test::TestRequest::get().to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+}
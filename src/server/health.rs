@@ -0,0 +1,122 @@
+use crate::server::config::ServerConfig;
+use crate::server::shutdown::Shutdown;
+use actix_web::{get, web, HttpResponse};
+use leptos::config::LeptosOptions;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Outcome of one readiness check.
+#[derive(Debug, Serialize)]
+pub struct ComponentStatus {
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ComponentStatus {
+    fn from_result(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Self {
+                status: "ok",
+                error: None,
+            },
+            Err(error) => Self {
+                status: "error",
+                error: Some(error),
+            },
+        }
+    }
+
+    fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub components: BTreeMap<&'static str, ComponentStatus>,
+}
+
+/// Liveness: answers as long as the process can serve requests at all.
+#[get("/healthz")]
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(HealthReport {
+        status: "ok",
+        components: BTreeMap::new(),
+    })
+}
+
+/// Readiness: whether this instance can do useful work. Returns 503 with the failing
+/// components so the orchestrator holds traffic back until they recover, or while the server
+/// drains before a shutdown. The server keeps no database, so the site files and upload storage
+/// are all there is to check.
+#[get("/readyz")]
+pub async fn readyz(
+    config: web::Data<ServerConfig>,
+    leptos_options: web::Data<LeptosOptions>,
+    shutdown: web::Data<Shutdown>,
+) -> HttpResponse {
+    let mut components = BTreeMap::new();
+
+    if shutdown.is_draining() {
+        components.insert(
+            "shutdown",
+            ComponentStatus::from_result(Err("server is shutting down".to_string())),
+        );
+    }
+
+    let site_root = PathBuf::from(leptos_options.site_root.as_ref());
+    let pkg_dir = site_root.join(leptos_options.site_pkg_dir.as_ref());
+    let site = web::block(move || check_site_root(&site_root, &pkg_dir)).await;
+    components.insert(
+        "site_root",
+        ComponentStatus::from_result(site.unwrap_or_else(|e| Err(e.to_string()))),
+    );
+
+    if config.features.uploads {
+        let upload_dir = config.upload_dir.clone();
+        let storage = web::block(move || check_storage(&upload_dir)).await;
+        components.insert(
+            "storage",
+            ComponentStatus::from_result(storage.unwrap_or_else(|e| Err(e.to_string()))),
+        );
+    }
+
+    let ready = components.values().all(ComponentStatus::is_ok);
+    let report = HealthReport {
+        status: if ready { "ok" } else { "unavailable" },
+        components,
+    };
+    if ready {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+fn check_site_root(site_root: &Path, pkg_dir: &Path) -> Result<(), String> {
+    if !site_root.is_dir() {
+        return Err(format!("{} is not a directory", site_root.display()));
+    }
+    if !pkg_dir.is_dir() {
+        return Err(format!(
+            "{} is missing; build the site with cargo leptos",
+            pkg_dir.display()
+        ));
+    }
+    Ok(())
+}
+
+// Writes and removes a probe file, so a read-only or full volume shows up before uploads fail.
+// Each probe gets its own name so concurrent checks, or instances sharing the volume, don't
+// remove each other's file.
+fn check_storage(upload_dir: &Path) -> Result<(), String> {
+    let probe = upload_dir.join(format!(".readyz-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(upload_dir)
+        .and_then(|_| std::fs::write(&probe, b"ok"))
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| format!("{} is not writable: {}", upload_dir.display(), e))
+}
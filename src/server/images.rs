@@ -0,0 +1,248 @@
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::{AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+use std::io::Cursor;
+
+/// Widths of the thumbnails generated for every uploaded image, when it is wider than them.
+pub const THUMBNAIL_WIDTHS: [u32; 3] = [160, 480, 1024];
+
+const MAX_DIMENSION: u32 = 10_000;
+const MAX_DECODE_BYTES: u64 = 256 * 1024 * 1024;
+/// Quantizer speed for re-encoded GIFs, from 1 (best) to 30 (fastest).
+const GIF_ENCODE_SPEED: i32 = 10;
+
+/// An uploaded image after metadata stripping, with its thumbnails.
+pub struct ProcessedImage {
+    pub original: Vec<u8>,
+    pub thumbnails: Vec<(u32, Vec<u8>)>,
+}
+
+/// Decodes an uploaded image, re-encodes it so EXIF, XMP and comments are dropped, and renders
+/// the thumbnails. The EXIF orientation is applied first, so photos keep their rotation once
+/// the tag is gone. This is CPU-bound, so call it from a blocking thread.
+pub fn process_image(data: &[u8], mime: &str) -> Result<ProcessedImage, String> {
+    let format = match mime {
+        "image/png" => ImageFormat::Png,
+        "image/jpeg" => ImageFormat::Jpeg,
+        "image/webp" => ImageFormat::WebP,
+        "image/gif" => ImageFormat::Gif,
+        _ => return Err(format!("Unsupported image type {}", mime)),
+    };
+
+    // GIFs are re-encoded frame by frame so animations survive without their extensions; the
+    // thumbnails come from the first frame
+    let (original, image) = match format {
+        ImageFormat::Gif => encode_gif(data)?,
+        _ => {
+            let image = decode(data, format)?;
+            (encode(&image, format)?, image)
+        }
+    };
+
+    let thumbnail_format = thumbnail_format(mime);
+    let thumbnails = THUMBNAIL_WIDTHS
+        .iter()
+        .filter(|&&width| width < image.width())
+        .map(|&width| {
+            let thumbnail = image.thumbnail(width, u32::MAX);
+            encode(&thumbnail, thumbnail_format).map(|bytes| (width, bytes))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ProcessedImage {
+        original,
+        thumbnails,
+    })
+}
+
+fn limits() -> Limits {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_BYTES);
+    limits
+}
+
+fn decode(data: &[u8], format: ImageFormat) -> Result<DynamicImage, String> {
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits());
+    let mut decoder = reader.into_decoder().map_err(|e| e.to_string())?;
+    limits()
+        .reserve(decoder.total_bytes())
+        .map_err(|e| e.to_string())?;
+    let orientation = decoder.orientation().map_err(|e| e.to_string())?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Copies only the frames, so comments, XMP and other application extensions are left behind,
+/// and returns the first frame alongside. Every decoded frame counts against the decode limit.
+fn encode_gif(data: &[u8]) -> Result<(Vec<u8>, DynamicImage), String> {
+    let mut decoder = GifDecoder::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    decoder.set_limits(limits()).map_err(|e| e.to_string())?;
+    let frame_bytes = decoder.total_bytes();
+    let mut budget = limits();
+
+    let mut bytes = Vec::new();
+    let mut first = None;
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut bytes, GIF_ENCODE_SPEED);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+        for frame in decoder.into_frames() {
+            budget.reserve(frame_bytes).map_err(|e| e.to_string())?;
+            let frame = frame.map_err(|e| e.to_string())?;
+            if first.is_none() {
+                first = Some(DynamicImage::ImageRgba8(frame.buffer().clone()));
+            }
+            encoder.encode_frame(frame).map_err(|e| e.to_string())?;
+        }
+    }
+    let first = first.ok_or_else(|| "GIF has no frames".to_string())?;
+    Ok((bytes, first))
+}
+
+/// Thumbnails are JPEG for photos and PNG for everything else, so transparency is kept.
+pub fn thumbnail_format(mime: &str) -> ImageFormat {
+    if mime == "image/jpeg" {
+        ImageFormat::Jpeg
+    } else {
+        ImageFormat::Png
+    }
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, String> {
+    // The JPEG and WebP encoders only accept 8-bit RGB(A)
+    let image = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+        ImageFormat::WebP if image.has_alpha() => DynamicImage::ImageRgba8(image.to_rgba8()),
+        ImageFormat::WebP => DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ => image.clone(),
+    };
+
+    let mut bytes = Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, format)
+        .map_err(|e| e.to_string())?;
+    Ok(bytes.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Delay, Frame, Rgba, RgbaImage};
+
+    const COMMENT: &[u8] = b"secret-location-comment";
+
+    fn encoded(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, _| {
+            image::Rgb([(x % 256) as u8, 80, 160])
+        }));
+        encode(&image, format).unwrap()
+    }
+
+    /// A JPEG carrying an EXIF block that says "rotate 90° clockwise", and a comment.
+    fn jpeg_with_metadata(width: u32, height: u32) -> Vec<u8> {
+        let jpeg = encoded(width, height, ImageFormat::Jpeg);
+        let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08".to_vec();
+        // One IFD entry: Orientation (0x0112), SHORT, count 1, value 6
+        exif.extend_from_slice(&[0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0]);
+
+        let mut data = jpeg[..2].to_vec();
+        for (marker, payload) in [(0xe1, exif.as_slice()), (0xfe, COMMENT)] {
+            data.extend_from_slice(&[0xff, marker]);
+            data.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+            data.extend_from_slice(payload);
+        }
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    /// A red then blue animated GIF with a comment extension before the trailer.
+    fn gif_with_comment(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            let frames = [Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255])].map(|color| {
+                Frame::from_parts(
+                    RgbaImage::from_pixel(width, height, color),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(100, 1),
+                )
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+        let trailer = data.pop().unwrap();
+        data.extend_from_slice(&[0x21, 0xfe, COMMENT.len() as u8]);
+        data.extend_from_slice(COMMENT);
+        data.extend_from_slice(&[0, trailer]);
+        data
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn jpeg_metadata_is_stripped_after_applying_orientation() {
+        let data = jpeg_with_metadata(40, 20);
+        assert!(contains(&data, b"Exif") && contains(&data, COMMENT));
+
+        let processed = process_image(&data, "image/jpeg").unwrap();
+        assert!(!contains(&processed.original, b"Exif"));
+        assert!(!contains(&processed.original, COMMENT));
+        let image = image::load_from_memory(&processed.original).unwrap();
+        assert_eq!((image.width(), image.height()), (20, 40));
+    }
+
+    #[test]
+    fn gif_is_reencoded_without_extensions() {
+        let data = gif_with_comment(32, 16);
+        assert!(contains(&data, COMMENT));
+
+        let processed = process_image(&data, "image/gif").unwrap();
+        assert!(!contains(&processed.original, COMMENT));
+        let frames = GifDecoder::new(Cursor::new(&processed.original))
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+    }
+
+    #[test]
+    fn gif_thumbnails_show_the_first_frame() {
+        let processed = process_image(&gif_with_comment(200, 100), "image/gif").unwrap();
+        let (width, bytes) = &processed.thumbnails[0];
+        assert_eq!(*width, 160);
+        let thumbnail = image::load_from_memory(bytes).unwrap().to_rgba8();
+        assert_eq!(thumbnail.get_pixel(80, 40), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn thumbnails_only_for_narrower_widths() {
+        let processed = process_image(&encoded(800, 400, ImageFormat::Png), "image/png").unwrap();
+        let widths: Vec<u32> = processed.thumbnails.iter().map(|(w, _)| *w).collect();
+        assert_eq!(widths, [160, 480]);
+        for (width, bytes) in &processed.thumbnails {
+            let thumbnail = image::load_from_memory(bytes).unwrap();
+            assert_eq!(thumbnail.width(), *width);
+            assert_eq!(thumbnail.height(), width / 2);
+        }
+
+        let small = process_image(&encoded(100, 100, ImageFormat::Png), "image/png").unwrap();
+        assert!(small.thumbnails.is_empty());
+    }
+
+    #[test]
+    fn rejects_images_over_the_decode_limits() {
+        let wide = encoded(MAX_DIMENSION + 1, 1, ImageFormat::Png);
+        assert!(process_image(&wide, "image/png").is_err());
+        assert!(process_image(b"not an image", "image/png").is_err());
+        assert!(process_image(&wide, "image/bmp").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{get, web, Error, HttpResponse};
+use prometheus::{
+    exponential_buckets, HistogramOpts, HistogramTimer, HistogramVec, IntCounter, IntCounterVec,
+    Opts, Registry, TextEncoder,
+};
+use std::time::Instant;
+
+/// Prometheus metrics for the server, kept in their own registry and shared by all workers.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    uploads: IntCounterVec,
+    upload_bytes: IntCounter,
+    storage_duration: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, String> {
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled"),
+            &["method", "route", "status"],
+        )
+        .map_err(|e| e.to_string())?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time to produce an HTTP response",
+            ),
+            &["method", "route"],
+        )
+        .map_err(|e| e.to_string())?;
+        let uploads = IntCounterVec::new(
+            Opts::new("uploads_total", "Stored uploads by kind"),
+            &["kind"],
+        )
+        .map_err(|e| e.to_string())?;
+        let upload_bytes = IntCounter::new("upload_bytes_total", "Bytes of stored uploads")
+            .map_err(|e| e.to_string())?;
+        let storage_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "storage_operation_duration_seconds",
+                "Time spent reading and writing stored data",
+            )
+            .buckets(exponential_buckets(0.0005, 2.0, 14).map_err(|e| e.to_string())?),
+            &["operation"],
+        )
+        .map_err(|e| e.to_string())?;
+
+        let registry = Registry::new_custom(Some("chat_stream".to_string()), None)
+            .map_err(|e| e.to_string())?;
+        registry
+            .register(Box::new(http_requests.clone()))
+            .and_then(|_| registry.register(Box::new(http_request_duration.clone())))
+            .and_then(|_| registry.register(Box::new(uploads.clone())))
+            .and_then(|_| registry.register(Box::new(upload_bytes.clone())))
+            .and_then(|_| registry.register(Box::new(storage_duration.clone())))
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            uploads,
+            upload_bytes,
+            storage_duration,
+        })
+    }
+
+    /// Counts a stored upload; `kind` is `image`, `voice` or `file`.
+    pub fn record_upload(&self, kind: &str, bytes: u64) {
+        self.uploads.with_label_values(&[kind]).inc();
+        self.upload_bytes.inc_by(bytes);
+    }
+
+    /// Times a storage operation, e.g. `upload_store` or `upload_load`.
+    pub fn time_storage(&self, operation: &str) -> HistogramTimer {
+        self.storage_duration
+            .with_label_values(&[operation])
+            .start_timer()
+    }
+
+    /// The registry in the Prometheus text exposition format.
+    pub fn render(&self) -> Result<String, String> {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Counts and times every request. Routes are labelled by their pattern, not the raw path,
+/// so room codes and upload ids don't blow up the number of series.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let started = Instant::now();
+
+    let response = next.call(req).await?;
+
+    if let Some(metrics) = metrics {
+        metrics
+            .http_requests
+            .with_label_values(&[method.as_str(), route.as_str(), response.status().as_str()])
+            .inc();
+        metrics
+            .http_request_duration
+            .with_label_values(&[method.as_str(), route.as_str()])
+            .observe(started.elapsed().as_secs_f64());
+    }
+    Ok(response)
+}
+
+#[get("/metrics")]
+pub async fn metrics_endpoint(metrics: web::Data<Metrics>) -> actix_web::Result<HttpResponse> {
+    let body = metrics.render().map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        ))
+        .body(body))
+}
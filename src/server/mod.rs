@@ -0,0 +1,11 @@
+pub mod assets;
+pub mod config;
+pub mod cors;
+pub mod health;
+pub mod images;
+pub mod metrics;
+pub mod preview;
+pub mod rate_limit;
+pub mod shutdown;
+pub mod telemetry;
+pub mod uploads;
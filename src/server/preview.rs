@@ -0,0 +1,519 @@
+use crate::format::{parse, Block, Inline};
+use crate::preview::LinkPreview;
+use crate::server::config::ServerConfig;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Client, Url};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAX_REDIRECTS: usize = 3;
+const MAX_TITLE_LEN: usize = 200;
+const MAX_DESCRIPTION_LEN: usize = 300;
+
+/// Limits for server-side link preview fetching.
+#[derive(Debug, Clone)]
+pub struct PreviewConfig {
+    pub enabled: bool,
+    pub timeout: Duration,
+    pub max_bytes: usize,
+    /// Lets previews reach loopback and private ranges. Only meant for tests and trusted networks.
+    pub allow_private_networks: bool,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout: Duration::from_secs(3),
+            max_bytes: 256 * 1024,
+            allow_private_networks: false,
+        }
+    }
+}
+
+impl PreviewConfig {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self {
+            enabled: config.features.link_previews,
+            ..Self::default()
+        }
+    }
+}
+
+/// Fetches OpenGraph metadata, refusing to connect anywhere but public HTTP(S) hosts.
+///
+/// There is deliberately no endpoint that takes a URL: that would make the server an open fetch
+/// proxy. Previews are fetched for links in messages the server itself handles.
+pub struct PreviewFetcher {
+    config: PreviewConfig,
+    client: Client,
+}
+
+impl PreviewFetcher {
+    pub fn new(config: PreviewConfig) -> Result<Self, String> {
+        let allow_private = config.allow_private_networks;
+        let client = Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.timeout)
+            .no_proxy()
+            .user_agent("chat_stream-link-preview")
+            .dns_resolver(Arc::new(PublicResolver { allow_private }))
+            .redirect(redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if !is_allowed_url(attempt.url(), allow_private) {
+                    attempt.error("redirect to a disallowed address")
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self { config, client })
+    }
+
+    /// Fetches a preview for the first link in a message, as the message renderer shows it.
+    /// Nothing calls this yet: messages don't pass through the server, so there is nothing to
+    /// attach a preview to.
+    pub async fn fetch_for_message(&self, text: &str) -> Result<Option<LinkPreview>, String> {
+        match first_link(text) {
+            Some(url) => self.fetch(&url).await,
+            None => Ok(None),
+        }
+    }
+
+    pub async fn fetch(&self, url: &str) -> Result<Option<LinkPreview>, String> {
+        if !self.config.enabled {
+            return Ok(None);
+        }
+
+        let url = Url::parse(url).map_err(|e| e.to_string())?;
+        if !is_allowed_url(&url, self.config.allow_private_networks) {
+            return Err("URL is not allowed".to_string());
+        }
+
+        let mut response = self
+            .client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Unexpected status {}", response.status()));
+        }
+
+        let is_html = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.to_ascii_lowercase().starts_with("text/html"));
+        if !is_html {
+            return Ok(None);
+        }
+
+        // The metadata lives in <head>, so a truncated body is still useful
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+            let remaining = self.config.max_bytes - body.len();
+            body.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+            if body.len() >= self.config.max_bytes {
+                break;
+            }
+        }
+
+        let final_url = response.url().clone();
+        Ok(parse_open_graph(
+            &String::from_utf8_lossy(&body),
+            &final_url,
+        ))
+    }
+}
+
+/// The first link in `text` outside code, with the same rules the renderer uses.
+fn first_link(text: &str) -> Option<String> {
+    fn in_inlines(inlines: &[Inline]) -> Option<String> {
+        inlines.iter().find_map(|inline| match inline {
+            Inline::Link { href, .. } if href.starts_with("http") => Some(href.clone()),
+            Inline::Bold(children) | Inline::Italic(children) => in_inlines(children),
+            _ => None,
+        })
+    }
+
+    parse(text).iter().find_map(|block| match block {
+        Block::Paragraph(inlines) => in_inlines(inlines),
+        Block::List { items, .. } => items.iter().find_map(|item| in_inlines(item)),
+        Block::CodeBlock { .. } => None,
+    })
+}
+
+/// Resolves hostnames, dropping any address a preview must not reach.
+struct PublicResolver {
+    allow_private: bool,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(resolve_public(
+            name.as_str().to_string(),
+            self.allow_private,
+        ))
+    }
+}
+
+async fn resolve_public(
+    host: String,
+    allow_private: bool,
+) -> Result<Addrs, Box<dyn std::error::Error + Send + Sync>> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+        .await?
+        .filter(|addr| allow_private || is_public_ip(addr.ip()))
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{host} does not resolve to a public address").into());
+    }
+    Ok(Box::new(addrs.into_iter()))
+}
+
+/// Checks the scheme and, for IP literals (which skip DNS), the address.
+fn is_allowed_url(url: &Url, allow_private: bool) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    match host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(ip) => allow_private || is_public_ip(ip),
+        Err(_) => true,
+    }
+}
+
+/// Whether `ip` is a globally routable unicast address.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                || a >= 240
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64)
+                // IETF protocol assignments, 192.0.0.0/24
+                || (a == 192 && b == 0 && c == 0)
+                // Benchmarking, 198.18.0.0/15
+                || (a == 198 && (b & 0xfe) == 18))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(mapped));
+            }
+            let segments = ip.segments();
+            let first = segments[0];
+            // Transition prefixes embed an IPv4 address that a gateway connects to, which could be
+            // private, so they are refused outright
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // IPv4-compatible, ::/96
+                || segments[..6] == [0; 6]
+                // NAT64, 64:ff9b::/96 and the local-use 64:ff9b:1::/48
+                || (first == 0x64 && segments[1] == 0xff9b)
+                // Discard-only, 100::/64
+                || segments[..4] == [0x100, 0, 0, 0]
+                // Teredo, 2001::/32
+                || (first == 0x2001 && segments[1] == 0)
+                // Documentation, 2001:db8::/32
+                || (first == 0x2001 && segments[1] == 0xdb8)
+                // 6to4, 2002::/16
+                || first == 0x2002
+                // Unique local, fc00::/7
+                || (first & 0xfe00) == 0xfc00
+                // Link local, fe80::/10
+                || (first & 0xffc0) == 0xfe80
+                // Site local (deprecated but still routed by some stacks), fec0::/10
+                || (first & 0xffc0) == 0xfec0)
+        }
+    }
+}
+
+/// Extracts OpenGraph tags (falling back to `<title>`) from an HTML document.
+pub fn parse_open_graph(html: &str, url: &Url) -> Option<LinkPreview> {
+    let lower = html.to_ascii_lowercase();
+    let mut title = None;
+    let mut description = None;
+    let mut site_name = None;
+
+    let mut pos = 0;
+    while let Some(start) = lower[pos..].find("<meta") {
+        let start = pos + start;
+        let end = lower[start..]
+            .find('>')
+            .map_or(html.len(), |end| start + end);
+        let attrs = attributes(&html[start + "<meta".len()..end]);
+        pos = end;
+
+        let key = attrs
+            .iter()
+            .find(|(k, _)| k == "property" || k == "name")
+            .map(|(_, v)| v.to_ascii_lowercase());
+        let content = attrs
+            .iter()
+            .find(|(k, _)| k == "content")
+            .map(|(_, v)| decode_entities(v));
+        let (Some(key), Some(content)) = (key, content) else {
+            continue;
+        };
+
+        let slot = match key.as_str() {
+            "og:title" => &mut title,
+            "og:description" | "description" => &mut description,
+            "og:site_name" => &mut site_name,
+            _ => continue,
+        };
+        // The first og: tag wins, but og:description overrides a plain description
+        if slot.is_none() || key == "og:description" {
+            *slot = Some(content);
+        }
+    }
+
+    let title = title.or_else(|| {
+        let start = lower.find("<title")?;
+        let start = start + lower[start..].find('>')? + 1;
+        let end = start + lower[start..].find("</title")?;
+        Some(decode_entities(&html[start..end]))
+    })?;
+    let title = truncate(title.trim(), MAX_TITLE_LEN);
+    if title.is_empty() {
+        return None;
+    }
+
+    Some(LinkPreview {
+        url: url.to_string(),
+        title,
+        description: description.map(|d| truncate(d.trim(), MAX_DESCRIPTION_LEN)),
+        site_name: site_name.map(|s| truncate(s.trim(), MAX_TITLE_LEN)),
+    })
+}
+
+/// Parses `key="value"` pairs from the inside of a tag. Keys are lowercased.
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut rest = tag.trim_start();
+
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace() || c == '/')
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_ascii_lowercase();
+        rest = rest[key_end..].trim_start();
+
+        let value = if let Some(after_eq) = rest.strip_prefix('=') {
+            let after_eq = after_eq.trim_start();
+            let (value, remaining) = match after_eq.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let body = &after_eq[1..];
+                    let end = body.find(quote).unwrap_or(body.len());
+                    (&body[..end], body.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after_eq.find(char::is_whitespace).unwrap_or(after_eq.len());
+                    (&after_eq[..end], &after_eq[end..])
+                }
+            };
+            rest = remaining.trim_start();
+            value.to_string()
+        } else {
+            String::new()
+        };
+
+        if key.is_empty() {
+            rest = rest.get(1..).unwrap_or("").trim_start();
+        } else {
+            attrs.push((key, value));
+        }
+    }
+
+    attrs
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    text.chars().take(max_chars).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Serves a single canned HTTP response on a local port and returns its base URL.
+    fn stand_in_server(content_type: &'static str, body: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        format!("http://{addr}/")
+    }
+
+    fn local_config() -> PreviewConfig {
+        PreviewConfig {
+            allow_private_networks: true,
+            ..PreviewConfig::default()
+        }
+    }
+
+    const PAGE: &str = r#"<html><head>
+        <title>Fallback</title>
+        <meta property="og:title" content="Chat &amp; Stream">
+        <meta name="description" content="plain">
+        <meta property='og:description' content='Real-time chat'>
+        <meta property="og:image" content="/cover.png" />
+        </head><body></body></html>"#;
+
+    #[test]
+    fn parses_open_graph_tags() {
+        let url = Url::parse("https://example.com/post").unwrap();
+        let preview = parse_open_graph(PAGE, &url).unwrap();
+        assert_eq!(preview.title, "Chat & Stream");
+        assert_eq!(preview.description.as_deref(), Some("Real-time chat"));
+    }
+
+    #[test]
+    fn falls_back_to_title() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let preview = parse_open_graph("<title>Just a title</title>", &url).unwrap();
+        assert_eq!(preview.title, "Just a title");
+        assert!(parse_open_graph("<p>nothing</p>", &url).is_none());
+    }
+
+    #[test]
+    fn rejects_non_public_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "169.254.169.254",
+            "100.64.0.1",
+            "::1",
+            "fd00::1",
+            "::ffff:192.168.0.1",
+            "::10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b:1::1",
+            "2002:a9fe:a9fe::1",
+            "2001:0:4136:e378::1",
+            "2001:db8::1",
+            "fec0::1",
+            "100::1",
+            "192.0.0.8",
+            "198.18.0.1",
+        ] {
+            assert!(
+                !is_public_ip(ip.parse().unwrap()),
+                "{ip} should be rejected"
+            );
+        }
+        assert!(is_public_ip("93.184.216.34".parse().unwrap()));
+        assert!(is_public_ip("2606:2800:220:1::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn previews_the_first_rendered_link() {
+        assert_eq!(
+            first_link("see **https://example.com/a** and https://example.org").as_deref(),
+            Some("https://example.com/a")
+        );
+        assert_eq!(
+            first_link("```\nhttps://example.com/in-code\n```\n[docs](mailto:a@b.c)"),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_other_schemes() {
+        for url in [
+            "file:///etc/passwd",
+            "ftp://example.com/",
+            "gopher://example.com/",
+        ] {
+            assert!(
+                !is_allowed_url(&Url::parse(url).unwrap(), true),
+                "{url} should be rejected"
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn fetches_preview_from_server() {
+        let url = stand_in_server("text/html; charset=utf-8", PAGE.to_string());
+        let fetcher = PreviewFetcher::new(local_config()).unwrap();
+        let preview = fetcher.fetch(&url).await.unwrap().unwrap();
+        assert_eq!(preview.title, "Chat & Stream");
+    }
+
+    #[actix_web::test]
+    async fn refuses_loopback_by_default() {
+        let url = stand_in_server("text/html", PAGE.to_string());
+        let fetcher = PreviewFetcher::new(PreviewConfig::default()).unwrap();
+        assert!(fetcher.fetch(&url).await.is_err());
+        assert!(fetcher.fetch("http://localhost/").await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn ignores_non_html() {
+        let url = stand_in_server("application/json", "{}".to_string());
+        let fetcher = PreviewFetcher::new(local_config()).unwrap();
+        assert_eq!(fetcher.fetch(&url).await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn stops_reading_at_size_limit() {
+        let body = format!("<title>Big</title>{}", "x".repeat(64 * 1024));
+        let url = stand_in_server("text/html", body);
+        let fetcher = PreviewFetcher::new(PreviewConfig {
+            max_bytes: 1024,
+            ..local_config()
+        })
+        .unwrap();
+        assert_eq!(fetcher.fetch(&url).await.unwrap().unwrap().title, "Big");
+    }
+
+    #[actix_web::test]
+    async fn disabled_config_skips_fetching() {
+        let fetcher = PreviewFetcher::new(PreviewConfig {
+            enabled: false,
+            ..PreviewConfig::default()
+        })
+        .unwrap();
+        assert_eq!(fetcher.fetch("https://example.com/").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Clients tracked before expired windows are swept out.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Fixed-window limiter keyed by client address, shared by all workers.
+///
+/// Clients are told apart by the connection's peer address, not `X-Forwarded-For`, which any
+/// client can set. Behind a reverse proxy every request shares the proxy's address, so the
+/// limit applies to the proxy as a whole.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    clients: Mutex<HashMap<IpAddr, Window>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    started: Instant,
+    count: u32,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    /// Counts a request from `client`, or returns how long until it may try again.
+    pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if clients.len() >= SWEEP_THRESHOLD {
+            clients.retain(|_, window| now.duration_since(window.started) < self.window);
+        }
+
+        let window = clients.entry(client).or_insert(Window {
+            started: now,
+            count: 0,
+        });
+        if now.duration_since(window.started) >= self.window {
+            *window = Window {
+                started: now,
+                count: 0,
+            };
+        }
+        if window.count >= self.limit {
+            return Err(self.window - now.duration_since(window.started));
+        }
+        window.count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(203, 0, 113, 1));
+    const B: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(203, 0, 113, 2));
+
+    #[test]
+    fn limits_each_client_separately() {
+        let limiter = RateLimiter::per_minute(2);
+        let now = Instant::now();
+        assert!(limiter.check_at(A, now).is_ok());
+        assert!(limiter.check_at(A, now).is_ok());
+        assert_eq!(limiter.check_at(A, now), Err(Duration::from_secs(60)));
+        assert!(limiter.check_at(B, now).is_ok());
+    }
+
+    #[test]
+    fn window_resets() {
+        let limiter = RateLimiter::per_minute(1);
+        let now = Instant::now();
+        assert!(limiter.check_at(A, now).is_ok());
+        assert_eq!(
+            limiter.check_at(A, now + Duration::from_secs(45)),
+            Err(Duration::from_secs(15))
+        );
+        assert!(limiter.check_at(A, now + Duration::from_secs(60)).is_ok());
+    }
+}
@@ -0,0 +1,156 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Coordinates a graceful shutdown across the server: readiness reports draining, and storage
+/// writes are waited for.
+#[derive(Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    draining: AtomicBool,
+    writes: AtomicUsize,
+    writes_done: Notify,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                draining: AtomicBool::new(false),
+                writes: AtomicUsize::new(0),
+                writes_done: Notify::new(),
+            }),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.inner.draining.load(Ordering::SeqCst)
+    }
+
+    /// Starts draining. Safe to call more than once.
+    pub fn begin(&self) {
+        self.inner.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Marks a storage write as in flight until the guard is dropped. Move the guard into the
+    /// blocking task doing the write, since that task outlives a cancelled request.
+    pub fn track_write(&self) -> WriteGuard {
+        self.inner.writes.fetch_add(1, Ordering::SeqCst);
+        WriteGuard {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Waits for in-flight storage writes, returning false if `timeout` passed first.
+    pub async fn wait_for_writes(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // Created before the check so a write finishing in between still wakes us
+            let finished = self.inner.writes_done.notified();
+            if self.inner.writes.load(Ordering::SeqCst) == 0 {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, finished).await.is_err() {
+                return false;
+            }
+        }
+    }
+}
+
+pub struct WriteGuard {
+    inner: Arc<Inner>,
+}
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        if self.inner.writes.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.writes_done.notify_waiters();
+        }
+    }
+}
+
+/// The signal that asked the server to stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopSignal {
+    /// SIGTERM, as sent by orchestrators during a rolling restart.
+    Terminate,
+    /// Ctrl-C, usually from a developer's terminal.
+    Interrupt,
+}
+
+/// Waits for SIGTERM or Ctrl-C.
+pub async fn stop_signal() -> StopSignal {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            return tokio::select! {
+                _ = terminate.recv() => StopSignal::Terminate,
+                _ = tokio::signal::ctrl_c() => StopSignal::Interrupt,
+            };
+        }
+    }
+
+    let _ = tokio::signal::ctrl_c().await;
+    StopSignal::Interrupt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn begin_starts_draining() {
+        let shutdown = Shutdown::new();
+        assert!(!shutdown.is_draining());
+        shutdown.clone().begin();
+        shutdown.begin();
+        assert!(shutdown.is_draining());
+    }
+
+    #[actix_web::test]
+    async fn no_writes_means_nothing_to_wait_for() {
+        let shutdown = Shutdown::new();
+        drop(shutdown.track_write());
+        assert!(shutdown.wait_for_writes(Duration::ZERO).await);
+    }
+
+    #[actix_web::test]
+    async fn waits_for_writes_on_blocking_threads() {
+        let shutdown = Shutdown::new();
+        let writes: Vec<_> = (1..=3)
+            .map(|n| {
+                let write = shutdown.track_write();
+                std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_millis(20 * n));
+                    drop(write);
+                })
+            })
+            .collect();
+
+        assert!(shutdown.wait_for_writes(TIMEOUT).await);
+        assert!(writes.iter().all(std::thread::JoinHandle::is_finished));
+    }
+
+    #[actix_web::test]
+    async fn gives_up_after_the_timeout() {
+        let shutdown = Shutdown::new();
+        let write = shutdown.track_write();
+        assert!(!shutdown.wait_for_writes(Duration::from_millis(20)).await);
+        drop(write);
+        assert!(shutdown.wait_for_writes(TIMEOUT).await);
+    }
+}
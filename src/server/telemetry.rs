@@ -0,0 +1,166 @@
+use crate::server::config::{LogFormat, ServerConfig};
+use crate::server::rate_limit::RateLimiter;
+use crate::telemetry::{ClientErrorReport, CLIENT_SESSION_HEADER, REQUEST_ID_HEADER};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::{ErrorBadRequest, ErrorPayloadTooLarge};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{post, web, Error, HttpMessage, HttpRequest, HttpResponse};
+use std::net::{IpAddr, Ipv4Addr};
+use tracing::{Level, Span};
+use tracing_actix_web::{DefaultRootSpanBuilder, RequestId, RootSpanBuilder};
+use tracing_subscriber::EnvFilter;
+
+const MAX_REPORT_BYTES: usize = 16 * 1024;
+const MAX_REPORT_FIELD_LEN: usize = 2000;
+/// A page in an error loop reports about once a second; more than this is someone filling the logs.
+const REPORTS_PER_MINUTE: u32 = 60;
+
+/// Installs the global tracing subscriber at the configured level and format.
+pub fn init_tracing(config: &ServerConfig) -> Result<(), String> {
+    let filter = EnvFilter::try_new(&config.log_level).map_err(|e| e.to_string())?;
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match config.log_format {
+        LogFormat::Text => subscriber.try_init(),
+        LogFormat::Json => subscriber
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init(),
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// Root span for every request. On top of the defaults (including `request_id`), it records the
+/// browser session from `X-Client-Session`, so server logs line up with client error reports.
+pub struct RequestSpan;
+
+impl RootSpanBuilder for RequestSpan {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let client_session = request
+            .headers()
+            .get(CLIENT_SESSION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|session| is_valid_session(session))
+            .unwrap_or_default();
+        tracing_actix_web::root_span!(request, client.session = %client_session)
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        if let Ok(response) = outcome {
+            span.in_scope(|| {
+                tracing::info!(status = response.status().as_u16(), "request completed")
+            });
+        }
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+/// Echoes the request id in an `X-Request-Id` response header, so the client can quote it in
+/// error reports. Must sit inside `TracingLogger`, which assigns the id.
+pub async fn request_id_header(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req.extensions().get::<RequestId>().copied();
+    let mut response = next.call(req).await?;
+    if let Some(value) = request_id.and_then(|id| HeaderValue::from_str(&id.to_string()).ok()) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(response)
+}
+
+/// Per-client budget for `client_error`, which anyone can call without an account.
+#[derive(Debug)]
+pub struct ClientErrorLimiter(pub RateLimiter);
+
+impl Default for ClientErrorLimiter {
+    fn default() -> Self {
+        Self(RateLimiter::per_minute(REPORTS_PER_MINUTE))
+    }
+}
+
+/// Receives error reports from the browser and logs them under the `client` target.
+/// The body is parsed by hand because `navigator.sendBeacon` can't always set a JSON content type.
+#[post("/client-errors")]
+pub async fn client_error(
+    req: HttpRequest,
+    limiter: web::Data<ClientErrorLimiter>,
+    body: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    let client = req
+        .peer_addr()
+        .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |addr| addr.ip());
+    if let Err(retry_after) = limiter.0.check(client) {
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after.as_secs().max(1)))
+            .finish());
+    }
+    if body.len() > MAX_REPORT_BYTES {
+        return Err(ErrorPayloadTooLarge("report is too large"));
+    }
+    let report: ClientErrorReport =
+        leptos::serde_json::from_slice(&body).map_err(ErrorBadRequest)?;
+    if !is_valid_session(&report.session) {
+        return Err(ErrorBadRequest("invalid session"));
+    }
+
+    // The message goes in a field rather than the log line itself, so it is written quoted and
+    // escaped: a report can't start a fake log line with an embedded newline
+    tracing::event!(
+        target: "client",
+        Level::WARN,
+        client.session = %report.session,
+        client.request_id = report.request_id.as_deref().map(truncate).unwrap_or_default(),
+        client.url = truncate(without_query(&report.url)),
+        client.message = truncate(&report.message),
+        "client error"
+    );
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Sessions are UUIDs; anything else is dropped rather than written into the logs
+fn is_valid_session(session: &str) -> bool {
+    !session.is_empty()
+        && session.len() <= 64
+        && session
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+// Reports from older clients sent the full URL, which could carry a room key in its fragment
+fn without_query(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or_default()
+}
+
+fn truncate(text: &str) -> &str {
+    match text.char_indices().nth(MAX_REPORT_FIELD_LEN) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_urls_lose_query_and_fragment() {
+        assert_eq!(
+            without_query("https://chat.example.com/chat?code=abc#key=secret"),
+            "https://chat.example.com/chat"
+        );
+        assert_eq!(
+            without_query("https://chat.example.com/chat#key=secret?x"),
+            "https://chat.example.com/chat"
+        );
+        assert_eq!(
+            without_query("https://chat.example.com/"),
+            "https://chat.example.com/"
+        );
+    }
+}
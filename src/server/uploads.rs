@@ -0,0 +1,386 @@
+use crate::attachment::Attachment;
+use crate::server::config::ServerConfig;
+use crate::server::images::{process_image, thumbnail_format};
+use crate::server::metrics::Metrics;
+use crate::server::rate_limit::RateLimiter;
+use crate::server::shutdown::Shutdown;
+use actix_files::NamedFile;
+use actix_multipart::{Field, Multipart};
+use actix_web::error::{
+    ErrorBadRequest, ErrorInternalServerError, ErrorNotFound, ErrorPayloadTooLarge,
+};
+use actix_web::http::header::{
+    self, ContentDisposition, DispositionParam, DispositionType, HeaderValue,
+};
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use futures_util::StreamExt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+const MAX_TEXT_FIELD_BYTES: usize = 4 * 1024;
+const MAX_WAVEFORM_BARS: usize = 100;
+
+/// Where uploads are stored and how large they may be.
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    pub dir: PathBuf,
+    pub max_bytes: usize,
+}
+
+impl UploadConfig {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self {
+            dir: config.upload_dir.clone(),
+            max_bytes: config.max_upload_bytes,
+        }
+    }
+}
+
+/// Per-client budget for `upload`, from `rate_limits.uploads_per_minute`.
+///
+/// There are no accounts to authenticate uploads against, so this is what stops one client from
+/// using the server as free file hosting.
+#[derive(Debug)]
+pub struct UploadLimiter(pub RateLimiter);
+
+impl UploadLimiter {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self(RateLimiter::per_minute(
+            config.rate_limits.uploads_per_minute,
+        ))
+    }
+}
+
+/// Accepts a multipart form with a `file` field and stores it under the room.
+///
+/// Voice notes also send `duration_ms` and `waveform` (comma-separated peaks) fields before the file.
+#[post("/uploads/{room}")]
+#[tracing::instrument(skip_all, fields(room = %room))]
+pub async fn upload(
+    req: HttpRequest,
+    config: web::Data<UploadConfig>,
+    limiter: web::Data<UploadLimiter>,
+    metrics: web::Data<Metrics>,
+    shutdown: web::Data<Shutdown>,
+    room: web::Path<String>,
+    mut payload: Multipart,
+) -> actix_web::Result<HttpResponse> {
+    let room = room.into_inner();
+    if !is_valid_id(&room) {
+        return Err(ErrorBadRequest("invalid room code"));
+    }
+    let client = req
+        .peer_addr()
+        .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |addr| addr.ip());
+    if let Err(retry_after) = limiter.0.check(client) {
+        tracing::info!(%client, "upload rate limit exceeded");
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after.as_secs().max(1)))
+            .body("too many uploads, try again later"));
+    }
+
+    let mut duration_ms = None;
+    let mut waveform = Vec::new();
+    while let Some(field) = payload.next().await {
+        let mut field = field?;
+        match field.name() {
+            Some("duration_ms") => {
+                duration_ms = read_text_field(&mut field).await?.parse().ok();
+                continue;
+            }
+            Some("waveform") => {
+                waveform = read_text_field(&mut field)
+                    .await?
+                    .split(',')
+                    .filter_map(|peak| peak.trim().parse().ok())
+                    .take(MAX_WAVEFORM_BARS)
+                    .collect();
+                continue;
+            }
+            Some("file") => {}
+            _ => continue,
+        }
+
+        let name = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .map(sanitize_filename)
+            .unwrap_or_else(|| "file".to_string());
+
+        let mut data = read_field(&mut field, config.max_bytes).await?;
+        let mut attachment = Attachment {
+            id: Uuid::new_v4().to_string(),
+            room,
+            name,
+            mime: sniff_mime(&data).to_string(),
+            size: data.len() as u64,
+            thumbnails: Vec::new(),
+            duration_ms: None,
+            waveform: Vec::new(),
+        };
+
+        let mut thumbnails = Vec::new();
+        if attachment.is_image() {
+            let mime = attachment.mime.clone();
+            let processed = web::block(move || process_image(&data, &mime))
+                .await?
+                .map_err(ErrorBadRequest)?;
+            // Re-encoding can grow a file that was heavily compressed; the limit is on what we store
+            if processed.original.len() > config.max_bytes {
+                return Err(ErrorPayloadTooLarge("image is too large once re-encoded"));
+            }
+            attachment.size = processed.original.len() as u64;
+            attachment.thumbnails = processed.thumbnails.iter().map(|(w, _)| *w).collect();
+            data = processed.original;
+            thumbnails = processed.thumbnails;
+        } else if let Some(mime) = duration_ms.and_then(|_| voice_note_mime(&attachment.mime)) {
+            attachment.mime = mime.to_string();
+            attachment.duration_ms = duration_ms;
+            attachment.waveform = waveform;
+        }
+        let timer = metrics.time_storage("upload_store");
+        store(&config, &shutdown, &attachment, data, thumbnails).await?;
+        timer.observe_duration();
+
+        let kind = if attachment.is_image() {
+            "image"
+        } else if attachment.duration_ms.is_some() {
+            "voice"
+        } else {
+            "file"
+        };
+        metrics.record_upload(kind, attachment.size);
+        tracing::info!(
+            upload.id = %attachment.id,
+            upload.mime = %attachment.mime,
+            upload.size = attachment.size,
+            "stored upload"
+        );
+
+        return Ok(HttpResponse::Created().json(attachment));
+    }
+
+    Err(ErrorBadRequest("missing file field"))
+}
+
+async fn read_field(field: &mut Field, limit: usize) -> actix_web::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk?;
+        if data.len() + chunk.len() > limit {
+            return Err(ErrorPayloadTooLarge("file is too large"));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+async fn read_text_field(field: &mut Field) -> actix_web::Result<String> {
+    let data = read_field(field, MAX_TEXT_FIELD_BYTES).await?;
+    String::from_utf8(data).map_err(ErrorBadRequest)
+}
+
+/// Browsers record into WebM, Ogg or MP4 containers, which sniff as video; a voice note is audio.
+fn voice_note_mime(sniffed: &str) -> Option<&'static str> {
+    match sniffed {
+        "video/webm" | "audio/webm" => Some("audio/webm"),
+        "audio/ogg" => Some("audio/ogg"),
+        "video/mp4" | "audio/mp4" | "audio/m4a" | "audio/x-m4a" => Some("audio/mp4"),
+        _ => None,
+    }
+}
+
+/// Serves a stored upload. Knowing the room code is what grants access to its files.
+#[get("/uploads/{room}/{id}")]
+pub async fn download(
+    req: HttpRequest,
+    config: web::Data<UploadConfig>,
+    metrics: web::Data<Metrics>,
+    path: web::Path<(String, String)>,
+) -> actix_web::Result<HttpResponse> {
+    let (room, id) = path.into_inner();
+    if !is_valid_id(&room) || !is_valid_id(&id) {
+        return Err(ErrorNotFound("not found"));
+    }
+
+    let attachment = {
+        let _timer = metrics.time_storage("upload_load");
+        load(&config, &room, &id).await?
+    };
+    let disposition = if attachment.is_image() {
+        DispositionType::Inline
+    } else {
+        DispositionType::Attachment
+    };
+    let mime = attachment
+        .mime
+        .parse()
+        .unwrap_or(actix_web::mime::APPLICATION_OCTET_STREAM);
+
+    let file = NamedFile::open_async(config.dir.join(&room).join(&id))
+        .await
+        .map_err(|_| ErrorNotFound("not found"))?
+        .set_content_type(mime)
+        .set_content_disposition(ContentDisposition {
+            disposition,
+            parameters: vec![DispositionParam::Filename(attachment.name)],
+        });
+
+    let mut response = file.into_response(&req);
+    response.headers_mut().insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    response.headers_mut().insert(
+        header::CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static("sandbox"),
+    );
+    Ok(response)
+}
+
+/// Serves one of an image's generated thumbnails.
+#[get("/uploads/{room}/{id}/thumb/{width}")]
+pub async fn thumbnail(
+    req: HttpRequest,
+    config: web::Data<UploadConfig>,
+    metrics: web::Data<Metrics>,
+    path: web::Path<(String, String, u32)>,
+) -> actix_web::Result<HttpResponse> {
+    let (room, id, width) = path.into_inner();
+    if !is_valid_id(&room) || !is_valid_id(&id) {
+        return Err(ErrorNotFound("not found"));
+    }
+
+    let attachment = {
+        let _timer = metrics.time_storage("upload_load");
+        load(&config, &room, &id).await?
+    };
+    if !attachment.thumbnails.contains(&width) {
+        return Err(ErrorNotFound("not found"));
+    }
+    let mime = thumbnail_format(&attachment.mime)
+        .to_mime_type()
+        .parse()
+        .unwrap_or(actix_web::mime::APPLICATION_OCTET_STREAM);
+
+    let file = NamedFile::open_async(config.dir.join(&room).join(thumbnail_file(&id, width)))
+        .await
+        .map_err(|_| ErrorNotFound("not found"))?
+        .set_content_type(mime);
+
+    let mut response = file.into_response(&req);
+    response.headers_mut().insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    Ok(response)
+}
+
+async fn store(
+    config: &UploadConfig,
+    shutdown: &Shutdown,
+    attachment: &Attachment,
+    data: Vec<u8>,
+    thumbnails: Vec<(u32, Vec<u8>)>,
+) -> actix_web::Result<()> {
+    let room_dir = config.dir.join(&attachment.room);
+    let file_path = room_dir.join(&attachment.id);
+    let meta_path = room_dir.join(format!("{}.json", attachment.id));
+    let meta = leptos::serde_json::to_vec(attachment).map_err(ErrorInternalServerError)?;
+    let id = attachment.id.clone();
+    // Held by the blocking task, so shutdown waits for the files even if the request is dropped
+    let write = shutdown.track_write();
+
+    web::block(move || -> std::io::Result<()> {
+        let _write = write;
+        std::fs::create_dir_all(&room_dir)?;
+        std::fs::write(file_path, data)?;
+        for (width, bytes) in thumbnails {
+            std::fs::write(room_dir.join(thumbnail_file(&id, width)), bytes)?;
+        }
+        // Written last so a download never sees metadata for a half-stored upload
+        std::fs::write(meta_path, meta)
+    })
+    .await?
+    .map_err(ErrorInternalServerError)
+}
+
+async fn load(config: &UploadConfig, room: &str, id: &str) -> actix_web::Result<Attachment> {
+    let meta_path = config.dir.join(room).join(format!("{id}.json"));
+    let meta = web::block(move || std::fs::read(meta_path))
+        .await?
+        .map_err(|_| ErrorNotFound("not found"))?;
+    leptos::serde_json::from_slice(&meta).map_err(ErrorInternalServerError)
+}
+
+fn thumbnail_file(id: &str, width: u32) -> String {
+    format!("{id}_{width}")
+}
+
+/// Detects the type from the file's magic bytes; the client's claimed type is never trusted.
+/// Anything textual is served as plain text so uploaded HTML can't run in the page's origin.
+fn sniff_mime(data: &[u8]) -> &'static str {
+    match infer::get(data).map(|kind| kind.mime_type()) {
+        Some(mime) if mime.starts_with("text/") => "text/plain",
+        Some(mime) => mime,
+        None if std::str::from_utf8(data).is_ok() => "text/plain",
+        None => "application/octet-stream",
+    }
+}
+
+/// Room codes and upload ids are UUIDs; restricting the alphabet keeps them out of path traversal.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn sanitize_filename(name: &str) -> String {
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(255)
+        .collect();
+    if name.is_empty() {
+        "file".to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizes_filenames() {
+        assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_filename("C:\\Users\\me\\notes.txt"), "notes.txt");
+        assert_eq!(sanitize_filename("line\nbreak.txt"), "linebreak.txt");
+        assert_eq!(sanitize_filename("dir/"), "file");
+        assert_eq!(sanitize_filename(&"a".repeat(300)).len(), 255);
+    }
+
+    #[test]
+    fn accepts_only_uuid_like_ids() {
+        assert!(is_valid_id("0b6f5c2e-7d1a-4c39-9e8f-2a4b6d8c0e1f"));
+        assert!(!is_valid_id(""));
+        assert!(!is_valid_id(".."));
+        assert!(!is_valid_id("a/b"));
+        assert!(!is_valid_id("id.json"));
+        assert!(!is_valid_id(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn sniffs_content_instead_of_trusting_names() {
+        assert_eq!(sniff_mime(b"<html><body>hi</body></html>"), "text/plain");
+        assert_eq!(sniff_mime(b"plain words"), "text/plain");
+        assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), "image/png");
+        assert_eq!(
+            sniff_mime(&[0xff, 0xfe, 0x00, 0x81]),
+            "application/octet-stream"
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use leptos::prelude::window;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{ErrorEvent, PromiseRejectionEvent};
+
+/// Header carrying the browser session id on requests the client makes itself.
+pub const CLIENT_SESSION_HEADER: &str = "x-client-session";
+/// Header the server answers with, naming the request in its logs.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// An error seen in the browser, posted to `/client-errors`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ClientErrorReport {
+    /// Random per page load, and sent with the client's own requests, to correlate the two.
+    pub session: String,
+    pub message: String,
+    /// Origin and path only.
+    pub url: String,
+    /// The server's id for the request that failed, when the error came from one.
+    pub request_id: Option<String>,
+}
+
+thread_local! {
+    static SESSION: String = uuid::Uuid::new_v4().to_string();
+}
+
+/// This page load's session id.
+pub fn session_id() -> String {
+    SESSION.with(String::clone)
+}
+
+/// The page the error happened on, without its query or fragment: an encrypted room's key
+/// travels in the fragment, and neither belongs in server logs.
+fn page_url() -> String {
+    let location = window().location();
+    format!(
+        "{}{}",
+        location.origin().unwrap_or_default(),
+        location.pathname().unwrap_or_default()
+    )
+}
+
+/// Sends an error report. A beacon is used so it is delivered even from a panic hook or while
+/// the page is unloading.
+pub fn report_error(message: &str, request_id: Option<String>) {
+    let report = ClientErrorReport {
+        session: session_id(),
+        message: message.to_string(),
+        url: page_url(),
+        request_id,
+    };
+    if let Ok(body) = leptos::serde_json::to_string(&report) {
+        let _ = window()
+            .navigator()
+            .send_beacon_with_opt_str("/client-errors", Some(&body));
+    }
+}
+
+/// Reports panics, uncaught errors and unhandled promise rejections, keeping the console output.
+pub fn install_error_reporting() {
+    std::panic::set_hook(Box::new(|info| {
+        console_error_panic_hook::hook(info);
+        report_error(&info.to_string(), None);
+    }));
+
+    let on_error = Closure::<dyn FnMut(ErrorEvent)>::new(|ev: ErrorEvent| {
+        report_error(&ev.message(), None);
+    });
+    let on_rejection =
+        Closure::<dyn FnMut(PromiseRejectionEvent)>::new(|ev: PromiseRejectionEvent| {
+            report_error(&format!("Unhandled rejection: {:?}", ev.reason()), None);
+        });
+    let window = window();
+    let _ = window.add_event_listener_with_callback("error", on_error.as_ref().unchecked_ref());
+    let _ = window.add_event_listener_with_callback(
+        "unhandledrejection",
+        on_rejection.as_ref().unchecked_ref(),
+    );
+    on_error.forget();
+    on_rejection.forget();
+}
@@ -0,0 +1,65 @@
+//! Fixtures shared by the integration tests. Each test file uses only some of them.
+#![allow(dead_code, unused_macros)]
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header::HeaderName;
+use std::path::PathBuf;
+
+/// An empty directory for one test, cleared of whatever an earlier run left in it.
+pub fn test_dir(suite: &str, test: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{suite}_{test}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn header_value<B>(response: &ServiceResponse<B>, name: HeaderName) -> Option<&str> {
+    response.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+/// The upload services as `main` mounts them, storing under `$dir` and allowing `$per_minute`
+/// uploads per client.
+macro_rules! uploads_app {
+    ($dir:expr, $max_bytes:expr, $per_minute:expr) => {
+        actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(
+                    chat_stream::server::uploads::UploadConfig {
+                        dir: $dir,
+                        max_bytes: $max_bytes,
+                    },
+                ))
+                .app_data(actix_web::web::Data::new(
+                    chat_stream::server::uploads::UploadLimiter(
+                        chat_stream::server::rate_limit::RateLimiter::per_minute($per_minute),
+                    ),
+                ))
+                .app_data(actix_web::web::Data::new(
+                    chat_stream::server::metrics::Metrics::new().unwrap(),
+                ))
+                .app_data(actix_web::web::Data::new(
+                    chat_stream::server::shutdown::Shutdown::new(),
+                ))
+                .service(chat_stream::server::uploads::upload)
+                .service(chat_stream::server::uploads::download)
+                .service(chat_stream::server::uploads::thumbnail),
+        )
+        .await
+    };
+    ($dir:expr) => {
+        uploads_app!($dir, 1024 * 1024, 100)
+    };
+}
+
+/// The `/pkg` service as `main` mounts it, serving `$assets`.
+macro_rules! pkg_app {
+    ($assets:expr) => {
+        actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new($assets))
+                .service(chat_stream::server::assets::pkg)
+                .wrap(actix_web::middleware::Compress::default()),
+        )
+        .await
+    };
+}
@@ -0,0 +1,38 @@
+#![cfg(feature = "ssr")]
+
+use actix_web::{middleware, test, web, App, HttpResponse};
+use chat_stream::server::metrics::{self, Metrics};
+
+#[actix_web::test]
+async fn requests_are_labelled_by_route_pattern() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Metrics::new().unwrap()))
+            .service(metrics::metrics_endpoint)
+            .route(
+                "/uploads/{room}/{id}",
+                web::get().to(|| async { HttpResponse::Ok().finish() }),
+            )
+            .wrap(middleware::from_fn(metrics::track_requests)),
+    )
+    .await;
+    for uri in ["/uploads/room-a/upload-1", "/uploads/room-b/upload-2"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        test::call_service(&app, req).await;
+    }
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let body = test::read_body(test::call_service(&app, req).await).await;
+    let body = std::str::from_utf8(&body).unwrap();
+    let series: Vec<&str> = body
+        .lines()
+        .filter(|line| line.starts_with("chat_stream_http_requests_total{"))
+        .filter(|line| line.contains("route=\"/uploads/{room}/{id}\""))
+        .collect();
+    assert_eq!(series.len(), 1, "{body}");
+    assert!(series[0].ends_with(" 2"), "{}", series[0]);
+    assert!(
+        !body.contains("room-a") && !body.contains("upload-2"),
+        "{body}"
+    );
+}
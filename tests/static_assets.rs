@@ -0,0 +1,181 @@
+#![cfg(feature = "ssr")]
+
+#[macro_use]
+mod common;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+use chat_stream::server::assets::Assets;
+use common::{header_value, test_dir};
+use std::path::PathBuf;
+
+const BUNDLE_JS: &str = "chat_stream.4d9f2c1ab7e05f36.js";
+const UNHASHED_WASM: &str = "chat_stream_bg.wasm";
+/// A bundle from an earlier build, which looks hashed but isn't listed in the current `hash.txt`.
+const STALE_JS: &str = "chat_stream.0123456789abcdef.js";
+const HASHES: &str = "js: 4d9f2c1ab7e05f36\nwasm: 9a8b7c6d5e4f3a2b\ncss: 1f2e3d4c5b6a7980\n";
+
+/// A pkg dir with a hashed JS bundle that has `.br` and `.gz` siblings, and an unhashed WASM
+/// file without any, laid out like a `cargo leptos build --precompress`, plus a bundle left over
+/// from an earlier build.
+fn pkg_dir(test: &str) -> PathBuf {
+    let dir = test_dir("static_assets", test);
+    std::fs::write(dir.join(BUNDLE_JS), "console.log('hello');\n".repeat(200)).unwrap();
+    std::fs::write(dir.join(format!("{BUNDLE_JS}.br")), b"brotli bytes").unwrap();
+    std::fs::write(dir.join(format!("{BUNDLE_JS}.gz")), b"gzip bytes").unwrap();
+    std::fs::write(dir.join(UNHASHED_WASM), b"\0asm".repeat(512)).unwrap();
+    std::fs::write(dir.join(STALE_JS), "console.log('old');\n").unwrap();
+    dir
+}
+
+/// The pkg dir served with `HASHES` as the build's `hash.txt`.
+fn assets(test: &str) -> Assets {
+    Assets::new(pkg_dir(test)).with_hashes("chat_stream", HASHES)
+}
+
+#[actix_web::test]
+async fn serves_brotli_sibling_when_accepted() {
+    let app = pkg_app!(assets("brotli"));
+    let req = test::TestRequest::get()
+        .uri(&format!("/pkg/{BUNDLE_JS}"))
+        .insert_header((header::ACCEPT_ENCODING, "gzip, deflate, br"))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        header_value(&response, header::CONTENT_ENCODING),
+        Some("br")
+    );
+    assert!(header_value(&response, header::CONTENT_TYPE)
+        .unwrap()
+        .contains("javascript"));
+    assert!(header_value(&response, header::VARY)
+        .unwrap()
+        .contains("accept-encoding"));
+    assert_eq!(test::read_body(response).await, "brotli bytes");
+}
+
+#[actix_web::test]
+async fn falls_back_to_gzip_sibling() {
+    let app = pkg_app!(assets("gzip"));
+    let req = test::TestRequest::get()
+        .uri(&format!("/pkg/{BUNDLE_JS}"))
+        .insert_header((header::ACCEPT_ENCODING, "gzip, br;q=0"))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(
+        header_value(&response, header::CONTENT_ENCODING),
+        Some("gzip")
+    );
+    assert_eq!(test::read_body(response).await, "gzip bytes");
+}
+
+#[actix_web::test]
+async fn serves_original_without_accept_encoding() {
+    let dir = pkg_dir("identity");
+    let app = pkg_app!(Assets::new(dir.clone()));
+    let req = test::TestRequest::get()
+        .uri(&format!("/pkg/{BUNDLE_JS}"))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header_value(&response, header::CONTENT_ENCODING), None);
+    assert_eq!(
+        test::read_body(response).await,
+        std::fs::read(dir.join(BUNDLE_JS)).unwrap()
+    );
+}
+
+#[actix_web::test]
+async fn compresses_files_without_precompressed_siblings() {
+    let app = pkg_app!(assets("compress"));
+    let req = test::TestRequest::get()
+        .uri(&format!("/pkg/{UNHASHED_WASM}"))
+        .insert_header((header::ACCEPT_ENCODING, "gzip"))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        header_value(&response, header::CONTENT_ENCODING),
+        Some("gzip")
+    );
+    assert_eq!(
+        header_value(&response, header::CONTENT_TYPE),
+        Some("application/wasm")
+    );
+}
+
+#[actix_web::test]
+async fn hashed_bundles_are_immutable() {
+    let app = pkg_app!(assets("immutable"));
+    let req = test::TestRequest::get()
+        .uri(&format!("/pkg/{BUNDLE_JS}"))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(
+        header_value(&response, header::CACHE_CONTROL),
+        Some("public, max-age=31536000, immutable")
+    );
+    assert!(header_value(&response, header::ETAG).is_some());
+}
+
+#[actix_web::test]
+async fn only_bundles_listed_in_the_hash_file_are_immutable() {
+    let app = pkg_app!(assets("stale"));
+    let req = test::TestRequest::get()
+        .uri(&format!("/pkg/{STALE_JS}"))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(
+        header_value(&response, header::CACHE_CONTROL),
+        Some("no-cache")
+    );
+
+    // Without a hash file nothing is known to be content-addressed
+    let app = pkg_app!(Assets::new(pkg_dir("no_hashes")));
+    let req = test::TestRequest::get()
+        .uri(&format!("/pkg/{BUNDLE_JS}"))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(
+        header_value(&response, header::CACHE_CONTROL),
+        Some("no-cache")
+    );
+}
+
+#[actix_web::test]
+async fn unhashed_files_revalidate_with_etag() {
+    let app = pkg_app!(assets("etag"));
+    let req = test::TestRequest::get()
+        .uri(&format!("/pkg/{UNHASHED_WASM}"))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(
+        header_value(&response, header::CACHE_CONTROL),
+        Some("no-cache")
+    );
+    let etag = header_value(&response, header::ETAG).unwrap().to_string();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/pkg/{UNHASHED_WASM}"))
+        .insert_header((header::IF_NONE_MATCH, etag))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[actix_web::test]
+async fn refuses_paths_outside_pkg() {
+    let app = pkg_app!(assets("traversal"));
+    for uri in ["/pkg/../Cargo.toml", "/pkg/.hidden", "/pkg/missing.js"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+    }
+}
@@ -0,0 +1,238 @@
+#![cfg(feature = "ssr")]
+
+#[macro_use]
+mod common;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+use chat_stream::attachment::Attachment;
+use common::{header_value, test_dir};
+use std::io::Cursor;
+use std::path::PathBuf;
+
+const ROOM: &str = "0b6f5c2e-7d1a-4c39-9e8f-2a4b6d8c0e1f";
+const BOUNDARY: &str = "chat-stream-test-boundary";
+const CLIENT: &str = "203.0.113.7:40000";
+
+fn upload_dir(test: &str) -> PathBuf {
+    test_dir("uploads", test)
+}
+
+/// A multipart body with a single `file` field.
+fn multipart(filename: &str, data: &[u8]) -> Vec<u8> {
+    let mut body = format!(
+        "--{BOUNDARY}\r\n\
+         Content-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\n\
+         Content-Type: application/octet-stream\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+    body
+}
+
+fn upload_request(room: &str, filename: &str, data: &[u8]) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(&format!("/uploads/{room}"))
+        .peer_addr(CLIENT.parse().unwrap())
+        .insert_header((
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        ))
+        .set_payload(multipart(filename, data))
+}
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    image::RgbImage::from_pixel(width, height, image::Rgb([200, 40, 40]))
+        .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+        .unwrap();
+    data
+}
+
+#[actix_web::test]
+async fn html_is_stored_and_served_as_plain_text_attachment() {
+    let app = uploads_app!(upload_dir("html"));
+    let html = b"<!DOCTYPE html><html><script>alert(document.cookie)</script></html>";
+    let response =
+        test::call_service(&app, upload_request(ROOM, "page.html", html).to_request()).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let attachment: Attachment = test::read_body_json(response).await;
+    assert_eq!(attachment.mime, "text/plain");
+    assert_eq!(attachment.size, html.len() as u64);
+
+    let req = test::TestRequest::get().uri(&attachment.url()).to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(header_value(&response, header::CONTENT_TYPE)
+        .unwrap()
+        .starts_with("text/plain"));
+    assert!(header_value(&response, header::CONTENT_DISPOSITION)
+        .unwrap()
+        .starts_with("attachment"));
+    assert_eq!(
+        header_value(&response, header::X_CONTENT_TYPE_OPTIONS),
+        Some("nosniff")
+    );
+    assert_eq!(
+        header_value(&response, header::CONTENT_SECURITY_POLICY),
+        Some("sandbox")
+    );
+    assert_eq!(test::read_body(response).await, &html[..]);
+}
+
+#[actix_web::test]
+async fn images_are_served_inline_with_thumbnails() {
+    let app = uploads_app!(upload_dir("image"));
+    let response = test::call_service(
+        &app,
+        upload_request(ROOM, "photo.png", &png(800, 600)).to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let attachment: Attachment = test::read_body_json(response).await;
+    assert_eq!(attachment.mime, "image/png");
+    assert!(!attachment.thumbnails.is_empty());
+
+    let req = test::TestRequest::get().uri(&attachment.url()).to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        header_value(&response, header::CONTENT_TYPE),
+        Some("image/png")
+    );
+    assert!(header_value(&response, header::CONTENT_DISPOSITION)
+        .unwrap()
+        .starts_with("inline"));
+
+    let width = attachment.thumbnails[0];
+    let req = test::TestRequest::get()
+        .uri(&format!("{}/thumb/{width}", attachment.url()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get()
+        .uri(&format!("{}/thumb/{}", attachment.url(), width + 1))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[actix_web::test]
+async fn filenames_are_reduced_to_their_last_component() {
+    let app = uploads_app!(upload_dir("filename"));
+    for (filename, expected) in [("../../etc/passwd", "passwd"), ("dir/", "file")] {
+        let response =
+            test::call_service(&app, upload_request(ROOM, filename, b"hi").to_request()).await;
+        assert_eq!(response.status(), StatusCode::CREATED, "{filename:?}");
+        let attachment: Attachment = test::read_body_json(response).await;
+        assert_eq!(attachment.name, expected, "{filename:?}");
+    }
+}
+
+#[actix_web::test]
+async fn rejects_files_over_the_size_limit() {
+    let dir = upload_dir("size_limit");
+    let app = uploads_app!(dir.clone(), 16, 100);
+    let response = test::call_service(
+        &app,
+        upload_request(ROOM, "big.txt", &[b'a'; 17]).to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(!dir.join(ROOM).exists());
+
+    let response = test::call_service(
+        &app,
+        upload_request(ROOM, "small.txt", &[b'a'; 16]).to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[actix_web::test]
+async fn rejects_images_that_grow_past_the_limit_when_reencoded() {
+    // Noise saved at the lowest JPEG quality comes out several times larger at the default one
+    let noise = image::RgbImage::from_fn(200, 200, |x, y| {
+        let v = (x.wrapping_mul(7919) ^ y.wrapping_mul(104_729)) as u8;
+        image::Rgb([v, v.wrapping_mul(3), v.wrapping_mul(5)])
+    });
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 1)
+        .encode_image(&noise)
+        .unwrap();
+
+    let dir = upload_dir("reencoded_size");
+    let app = uploads_app!(dir.clone(), jpeg.len(), 100);
+    let response =
+        test::call_service(&app, upload_request(ROOM, "noise.jpg", &jpeg).to_request()).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(!dir.join(ROOM).exists());
+}
+
+#[actix_web::test]
+async fn rejects_ids_outside_the_allowed_alphabet() {
+    let app = uploads_app!(upload_dir("ids"));
+    for room in ["..", "room.json", "a".repeat(65).as_str(), "%2E%2E"] {
+        let response =
+            test::call_service(&app, upload_request(room, "a.txt", b"hi").to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{room:?}");
+    }
+
+    let response =
+        test::call_service(&app, upload_request(ROOM, "a.txt", b"hi").to_request()).await;
+    let attachment: Attachment = test::read_body_json(response).await;
+    for uri in [
+        format!("/uploads/{ROOM}/{}.json", attachment.id),
+        format!("/uploads/{ROOM}/%2E%2E"),
+        format!("/uploads/%2E%2E/{}", attachment.id),
+    ] {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+    }
+}
+
+#[actix_web::test]
+async fn requires_a_file_field() {
+    let app = uploads_app!(upload_dir("missing_file"));
+    let body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"duration_ms\"\r\n\r\n1200\r\n--{BOUNDARY}--\r\n"
+    );
+    let req = test::TestRequest::post()
+        .uri(&format!("/uploads/{ROOM}"))
+        .peer_addr(CLIENT.parse().unwrap())
+        .insert_header((
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        ))
+        .set_payload(body)
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[actix_web::test]
+async fn limits_uploads_per_client() {
+    let app = uploads_app!(upload_dir("rate_limit"), 1024, 2);
+    for _ in 0..2 {
+        let response =
+            test::call_service(&app, upload_request(ROOM, "a.txt", b"hi").to_request()).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+    let response =
+        test::call_service(&app, upload_request(ROOM, "a.txt", b"hi").to_request()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(header_value(&response, header::RETRY_AFTER).is_some());
+
+    let req = upload_request(ROOM, "a.txt", b"hi")
+        .peer_addr("198.51.100.4:40000".parse().unwrap())
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::CREATED
+    );
+}